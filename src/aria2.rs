@@ -1,115 +1,433 @@
 #![allow(non_snake_case)]
-#![allow(dead_code)]
-const ARIA2_URL: &'static str = "http://localhost:6800/jsonrpc";
-
-use reqwest::{
-    Error, {Client, Response},
+use crate::config::config;
+use crate::downloader::{
+    DownloadFile, DownloadState, DownloadStatus, Downloader, Event, Notification,
+};
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::{
+    sync::mpsc,
+    time::{sleep, Duration},
 };
-use serde::{Deserialize, Serialize};
-use serde_json::json;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{debug, info, warn};
+
+/// Longest wait between two websocket reconnects, in seconds.
+const RECONNECT_MAX_SECS: u64 = 60;
 
 #[derive(Serialize, Deserialize)]
-struct AddUriRsp {
-    id: String,
-    jsonrpc: String,
-    result: String,
+struct RpcError {
+    code: i64,
+    message: String,
 }
 
-#[derive(Serialize, Deserialize)]
-struct Uris {
-    status: String,
-    uri: String,
+impl From<RpcError> for Error {
+    fn from(e: RpcError) -> Self {
+        Error::Aria2Rpc {
+            code: e.code,
+            message: e.message,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
-struct Files {
-    completedLength: String,
-    index: String,
-    length: String,
-    path: String,
-    selected: String,
-    uris: Vec<Uris>,
+struct RpcRsp<T> {
+    id: Value,
+    jsonrpc: String,
+    #[serde(default = "Option::default")]
+    result: Option<T>,
+    #[serde(default)]
+    error: Option<RpcError>,
 }
-#[derive(Serialize, Deserialize)]
-struct Status {
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Uris {
+    pub status: String,
+    pub uri: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Files {
+    pub completedLength: String,
+    pub index: String,
+    pub length: String,
+    pub path: String,
+    pub selected: String,
+    pub uris: Vec<Uris>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    #[serde(default)]
+    pub bitfield: Option<String>,
+    pub completedLength: String,
+    pub connections: String,
+    pub dir: String,
+    pub downloadSpeed: String,
+    pub files: Vec<Files>,
+    pub gid: String,
+    pub numPieces: String,
+    pub pieceLength: String,
+    /// active, waiting, paused, error, complete or removed
+    pub status: String,
+    pub totalLength: String,
+    pub uploadLength: String,
+    pub uploadSpeed: String,
+    #[serde(default)]
+    pub infoHash: Option<String>,
+    #[serde(default)]
+    pub numSeeders: Option<String>,
+    #[serde(default)]
+    pub errorCode: Option<String>,
     #[serde(default)]
-    bitfield: Option<String>,
-    completedLength: String,
-    connections: String,
-    dir: String,
-    downloadSpeed: String,
-    files: Vec<Files>,
+    pub errorMessage: Option<String>,
+    /// gids started by this download, e.g. the torrent download of a magnet
+    #[serde(default)]
+    pub followedBy: Option<Vec<String>>,
+    #[serde(default)]
+    pub following: Option<String>,
+    #[serde(default)]
+    pub belongsTo: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GlobalStat {
+    pub downloadSpeed: String,
+    pub uploadSpeed: String,
+    pub numActive: String,
+    pub numWaiting: String,
+    pub numStopped: String,
+    pub numStoppedTotal: String,
+}
+
+fn event_from_method(method: &str) -> Option<Event> {
+    match method {
+        "aria2.onDownloadComplete" => Some(Event::Complete),
+        "aria2.onBtDownloadComplete" => Some(Event::BtComplete),
+        "aria2.onDownloadError" => Some(Event::Error),
+        "aria2.onDownloadStop" => Some(Event::Stop),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcNotification {
+    method: String,
+    params: Vec<NotificationParams>,
+}
+
+#[derive(Deserialize)]
+struct NotificationParams {
     gid: String,
-    numPieces: String,
-    pieceLength: String,
-    status: String,
-    totalLength: String,
-    uploadLength: String,
-    uploadSpeed: String,
 }
-#[derive(Serialize, Deserialize)]
-struct TellStatusRsp {
-    id: String,
-    jsonrpc: String,
-    result: Status,
-}
-
-pub async fn download(uri: &str, dir: &str, out: &str) -> Result<String, Error> {
-    let req = json!({
-        "id": "w3n9",
-        "method": "aria2.addUri",
-        "params": [
-            [ uri ],
-            {
-                "dir": dir,
-                "out": out,
-                "referer": "*"
+
+fn parse_notification(text: &str) -> Vec<Notification> {
+    let Ok(n) = serde_json::from_str::<RpcNotification>(text) else {
+        // responses to requests, we never send any over the websocket
+        return Vec::new();
+    };
+    let Some(event) = event_from_method(&n.method) else {
+        return Vec::new();
+    };
+    n.params
+        .into_iter()
+        .map(|p| Notification { event, id: p.gid })
+        .collect()
+}
+
+/// `select-file` option of the files of `indexes`, aria2 counts them from 1.
+fn select_file(indexes: &[u32]) -> String {
+    let files: Vec<String> = indexes.iter().map(|i| (i + 1).to_string()).collect();
+    files.join(",")
+}
+
+/// Forwards the notifications of the aria2 websocket at `url` to `tx`,
+/// reconnecting with backoff whenever the connection drops. Returns once `tx`
+/// is closed.
+pub async fn subscribe(url: &str, tx: mpsc::Sender<Notification>) {
+    let mut backoff = 1;
+    loop {
+        match connect_async(url).await {
+            Ok((mut ws, _)) => {
+                info!("aria2 websocket {} connected", url);
+                backoff = 1;
+                while let Some(msg) = ws.next().await {
+                    let text = match msg {
+                        Ok(Message::Text(text)) => text,
+                        Ok(Message::Close(_)) => break,
+                        Ok(_) => continue,
+                        Err(e) => {
+                            warn!("aria2 websocket {} error:{:?}", url, e);
+                            break;
+                        }
+                    };
+                    for n in parse_notification(&text) {
+                        debug!("aria2 {:?} gid:{}", n.event, n.id);
+                        if tx.send(n).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                warn!("aria2 websocket {} closed, reconnect in {}s", url, backoff);
             }
-        ]
-    });
-
-    let json: AddUriRsp = Client::new()
-        .post(ARIA2_URL)
-        .json(&req)
-        .send()
-        .await?
-        .json()
-        .await?;
-    // let rsp = Client::new().post(ARIA2_URL).json(&req).send().await?;
-    // println!("download rsp:{:?}", rsp);
-    // let json: AddUriRsp = rsp.json().await?;
-    Ok(json.result)
-}
-
-pub async fn jsonrpc(method: &str, uid: &str) -> Result<Response, Error> {
-    let req = json!({
-        "id": "w3n9",
-        "method": method,
-        "params": [uid]
-    });
-
-    Client::new().post(ARIA2_URL).json(&req).send().await
-}
-
-pub async fn pause(uid: &str) -> Result<Response, Error> {
-    jsonrpc("aria2.pause", uid).await
-}
-pub async fn unpause(uid: &str) -> Result<Response, Error> {
-    jsonrpc("aria2.unpause", uid).await
-}
-pub async fn remove(uid: &str) -> Result<Response, Error> {
-    jsonrpc("aria2.remove", uid).await
-}
-
-pub async fn status(uid: &str) -> Result<(String, String, String), Error> {
-    let rsp = jsonrpc("aria2.tellStatus", uid).await?;
-    // let rsp = rsp.text().await?;
-    // println!("{uid} status rsp: {rsp}");
-    // let r: TellStatusRsp = serde_json::from_str(rsp.as_str()).unwrap();
-    let r: TellStatusRsp = rsp.json().await?;
-    Ok((
-        r.result.status,
-        r.result.completedLength,
-        r.result.totalLength,
-    ))
+            Err(e) => warn!(
+                "connect aria2 websocket {} error:{:?}, retry in {}s",
+                url, e, backoff
+            ),
+        }
+        if tx.is_closed() {
+            return;
+        }
+        sleep(Duration::from_secs(backoff)).await;
+        backoff = (backoff * 2).min(RECONNECT_MAX_SECS);
+    }
+}
+
+/// JSON-RPC client of an aria2 instance.
+pub struct Aria2 {
+    client: Client,
+    url: String,
+    /// `--rpc-secret` of aria2, sent as `token:<secret>`
+    token: Option<String>,
+    id: AtomicU64,
+}
+
+impl Aria2 {
+    pub fn new(url: &str, secret: Option<&str>) -> Self {
+        Aria2 {
+            client: Client::new(),
+            url: url.to_string(),
+            token: secret.map(|s| format!("token:{s}")),
+            id: AtomicU64::new(1),
+        }
+    }
+
+    fn params(&self, mut params: Vec<Value>) -> Vec<Value> {
+        if let Some(token) = &self.token {
+            params.insert(0, Value::String(token.clone()));
+        }
+        params
+    }
+
+    async fn request<T: DeserializeOwned>(&self, method: &str, params: Vec<Value>) -> Result<T> {
+        let req = json!({
+            "jsonrpc": "2.0",
+            "id": self.id.fetch_add(1, Ordering::Relaxed).to_string(),
+            "method": method,
+            "params": params,
+        });
+
+        let rsp: RpcRsp<T> = self
+            .client
+            .post(&self.url)
+            .json(&req)
+            .send()
+            .await
+            .map_err(Error::Aria2)?
+            .json()
+            .await
+            .map_err(Error::Aria2)?;
+        match (rsp.result, rsp.error) {
+            (_, Some(e)) => Err(e.into()),
+            (Some(result), None) => Ok(result),
+            (None, None) => Err(Error::parse(format!("{method} returned no result"))),
+        }
+    }
+
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Vec<Value>) -> Result<T> {
+        self.request(method, self.params(params)).await
+    }
+
+    /// Runs `calls` in one request, each of them succeeds or fails on its own.
+    pub async fn multicall(&self, calls: &[(&str, Vec<Value>)]) -> Result<Vec<Result<Value>>> {
+        let calls: Vec<_> = calls
+            .iter()
+            .map(|(method, params)| {
+                json!({
+                    "methodName": method,
+                    "params": self.params(params.clone()),
+                })
+            })
+            .collect();
+        // system.multicall itself takes no token, only the calls inside do
+        let rsp: Vec<Value> = self.request("system.multicall", vec![json!(calls)]).await?;
+        Ok(rsp
+            .into_iter()
+            .map(|r| match r {
+                // results come wrapped in a one element array
+                Value::Array(mut v) if v.len() == 1 => Ok(v.remove(0)),
+                other => match serde_json::from_value::<RpcError>(other) {
+                    Ok(e) => Err(e.into()),
+                    Err(e) => Err(Error::parse(e)),
+                },
+            })
+            .collect())
+    }
+
+    /// Adds a download and returns its gid.
+    pub async fn add_uri(&self, uris: &[&str], options: Map<String, Value>) -> Result<String> {
+        self.call("aria2.addUri", vec![json!(uris), Value::Object(options)])
+            .await
+    }
+
+    pub async fn pause(&self, gid: &str) -> Result<String> {
+        self.call("aria2.pause", vec![json!(gid)]).await
+    }
+
+    pub async fn unpause(&self, gid: &str) -> Result<String> {
+        self.call("aria2.unpause", vec![json!(gid)]).await
+    }
+
+    pub async fn remove(&self, gid: &str) -> Result<String> {
+        self.call("aria2.remove", vec![json!(gid)]).await
+    }
+
+    /// Drops a stopped download from aria2's memory.
+    pub async fn remove_download_result(&self, gid: &str) -> Result<String> {
+        self.call("aria2.removeDownloadResult", vec![json!(gid)])
+            .await
+    }
+
+    pub async fn tell_status(&self, gid: &str) -> Result<Status> {
+        self.call("aria2.tellStatus", vec![json!(gid)]).await
+    }
+
+    /// Statuses of several downloads in a single request.
+    pub async fn tell_statuses(&self, gids: &[&str]) -> Result<Vec<Result<Status>>> {
+        let calls: Vec<_> = gids
+            .iter()
+            .map(|gid| ("aria2.tellStatus", vec![json!(gid)]))
+            .collect();
+        Ok(self
+            .multicall(&calls)
+            .await?
+            .into_iter()
+            .map(|r| r.and_then(|v| serde_json::from_value(v).map_err(Error::parse)))
+            .collect())
+    }
+
+    pub async fn tell_active(&self) -> Result<Vec<Status>> {
+        self.call("aria2.tellActive", vec![]).await
+    }
+
+    pub async fn tell_waiting(&self, offset: i64, num: u32) -> Result<Vec<Status>> {
+        self.call("aria2.tellWaiting", vec![json!(offset), json!(num)])
+            .await
+    }
+
+    pub async fn tell_stopped(&self, offset: i64, num: u32) -> Result<Vec<Status>> {
+        self.call("aria2.tellStopped", vec![json!(offset), json!(num)])
+            .await
+    }
+
+    pub async fn get_files(&self, gid: &str) -> Result<Vec<Files>> {
+        self.call("aria2.getFiles", vec![json!(gid)]).await
+    }
+
+    pub async fn get_global_stat(&self) -> Result<GlobalStat> {
+        self.call("aria2.getGlobalStat", vec![]).await
+    }
+
+    pub async fn change_option(&self, gid: &str, options: Map<String, Value>) -> Result<()> {
+        let _: String = self
+            .call(
+                "aria2.changeOption",
+                vec![json!(gid), Value::Object(options)],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Downloader for Aria2 {
+    async fn add(&self, uri: &str, dir: &str, name: Option<&str>) -> Result<String> {
+        self.add_files(uri, dir, name, &[]).await
+    }
+
+    async fn add_files(
+        &self,
+        uri: &str,
+        dir: &str,
+        _name: Option<&str>,
+        indexes: &[u32],
+    ) -> Result<String> {
+        let mut options = Map::new();
+        options.insert("dir".to_string(), json!(dir));
+        options.insert("referer".to_string(), json!("*"));
+        if !indexes.is_empty() {
+            options.insert("select-file".to_string(), json!(select_file(indexes)));
+        }
+        self.add_uri(&[uri], options).await
+    }
+
+    async fn status(&self, id: &str) -> Result<DownloadStatus> {
+        let status = self.tell_status(id).await?;
+        let state = match status.status.as_str() {
+            "active" => DownloadState::Active,
+            "waiting" => DownloadState::Waiting,
+            "paused" => DownloadState::Paused,
+            "complete" => DownloadState::Complete,
+            "removed" => DownloadState::Removed,
+            _ => DownloadState::Failed(format!(
+                "code:{} {}",
+                status.errorCode.as_deref().unwrap_or_default(),
+                status.errorMessage.as_deref().unwrap_or_default()
+            )),
+        };
+        Ok(DownloadStatus {
+            id: status.gid,
+            state,
+            total: status.totalLength.parse().unwrap_or_default(),
+            completed: status.completedLength.parse().unwrap_or_default(),
+            info_hash: status.infoHash,
+            followed_by: status.followedBy.and_then(|f| f.into_iter().next()),
+        })
+    }
+
+    async fn pause(&self, id: &str) -> Result<()> {
+        Aria2::pause(self, id).await.map(|_| ())
+    }
+
+    async fn remove(&self, id: &str) -> Result<()> {
+        // stopped downloads can't be removed, only their results dropped
+        if Aria2::remove(self, id).await.is_err() {
+            self.remove_download_result(id).await?;
+        }
+        Ok(())
+    }
+
+    async fn files(&self, id: &str) -> Result<Vec<DownloadFile>> {
+        Ok(self
+            .get_files(id)
+            .await?
+            .into_iter()
+            .map(|f| DownloadFile {
+                index: f.index.parse::<u32>().unwrap_or_default().saturating_sub(1),
+                path: f.path,
+                length: f.length.parse().unwrap_or_default(),
+                completed: f.completedLength.parse().unwrap_or_default(),
+                selected: f.selected == "true",
+            })
+            .collect())
+    }
+
+    async fn select(&self, id: &str, indexes: &[u32]) -> Result<()> {
+        let mut options = Map::new();
+        options.insert("select-file".to_string(), json!(select_file(indexes)));
+        self.change_option(id, options).await
+    }
+
+    async fn subscribe(&self, tx: mpsc::Sender<Notification>) {
+        subscribe(&config().aria2.ws_url(), tx).await
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(config().aria2.reconcile_secs)
+    }
 }
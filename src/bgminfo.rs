@@ -1,6 +1,46 @@
-const DEFAULT_PATH: &str = "D:/download";
-use crate::db::db;
-#[derive(Debug)]
+use crate::config::config;
+use crate::db::{collect_rows, db};
+use crate::error::Result;
+use crate::state::BgmState;
+use clap::ValueEnum;
+use rusqlite::{types::Type, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// How the episodes of a show are counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Numbering {
+    /// from 1 every season
+    Season,
+    /// on from the earlier seasons, the season's numbers plus `offset`
+    Absolute,
+}
+
+impl Numbering {
+    fn as_str(self) -> &'static str {
+        match self {
+            Numbering::Season => "season",
+            Numbering::Absolute => "absolute",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "season" => Some(Numbering::Season),
+            "absolute" => Some(Numbering::Absolute),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Numbering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Bgm {
     pub id: u32,
     pub name: String,
@@ -8,41 +48,238 @@ pub struct Bgm {
     pub start_date: String,
     pub weekday: u8,
     pub clock: u8,
-    pub episode: u8,
-    pub episode_count: u8,
+    pub episode: u32,
+    pub episode_count: u32,
     pub regex: String,
     pub path: String,
-    pub state: u8,
-}
-
-pub fn get_new_bgms() -> Result<Vec<Bgm>, Box<dyn std::error::Error>> {
-    let ctx = db().lock()?;
-    let mut stmt = ctx.prepare("SELECT * FROM bgm WHERE state = ?")?;
-    let bgms = stmt.query_map([0], |row| {
-        Ok(Bgm {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            chinese: row.get(2).unwrap_or_default(),
-            start_date: row.get(3).unwrap_or_default(),
-            weekday: row.get(4)?,
-            clock: row.get(5).unwrap_or_default(),
-            episode: row.get(6).unwrap_or_default(),
-            episode_count: row.get(7).unwrap_or_default(),
-            regex: row.get(8)?,
-            path: row.get(9).unwrap_or(DEFAULT_PATH.to_string()),
-            state: row.get(10)?,
-        })
-    })?;
-
-    let mut result: Vec<Bgm> = Vec::new();
-    for bgm in bgms {
-        result.push(bgm?);
-    }
-    Ok(result)
-}
-
-pub fn update_bgm_state(bgm: Bgm) -> Result<(), Box<dyn std::error::Error>> {
-    let ctx = db().lock()?;
+    pub state: BgmState,
+    /// torrent source specs, empty for `source.default` of the config
+    pub sources: Vec<String>,
+    /// hours a task waits for a preferred group once it has candidates
+    pub grace_hours: u32,
+    /// hours after airing a done task keeps looking for a better release
+    pub upgrade_hours: u32,
+    /// absolute numbers are the season's plus this, e.g. 12 for a second cour
+    pub offset: u32,
+    /// numbering of the tasks, `episode` and `episode_count` count per season
+    pub numbering: Numbering,
+    /// numberings releases may use, empty for both
+    pub accept: Vec<Numbering>,
+}
+
+impl Bgm {
+    /// The sources releases of this bgm are looked for in.
+    pub fn sources(&self) -> &[String] {
+        if self.sources.is_empty() {
+            &config().source.default
+        } else {
+            &self.sources
+        }
+    }
+
+    /// Episode of the task for the season's `episode`.
+    pub fn task_episode(&self, episode: u32) -> Option<u32> {
+        match self.numbering {
+            Numbering::Season => Some(episode),
+            Numbering::Absolute => episode.checked_add(self.offset),
+        }
+    }
+
+    /// Episode of the season for the task's `episode`.
+    pub fn season_episode(&self, episode: u32) -> Option<u32> {
+        match self.numbering {
+            Numbering::Season => Some(episode),
+            Numbering::Absolute => episode.checked_sub(self.offset),
+        }
+    }
+
+    /// Numbers releases may give the task's `episode`, the task's own
+    /// numbering first.
+    pub fn release_episodes(&self, episode: u32) -> Vec<u32> {
+        let Some(season) = self.season_episode(episode) else {
+            return vec![episode];
+        };
+        let accepted = if self.accept.is_empty() {
+            vec![self.numbering, Numbering::Season, Numbering::Absolute]
+        } else {
+            let mut accepted = self.accept.clone();
+            accepted.sort_by_key(|n| *n != self.numbering);
+            accepted
+        };
+        let mut numbers = Vec::new();
+        for numbering in accepted {
+            let number = match numbering {
+                Numbering::Season => season,
+                Numbering::Absolute => season + self.offset,
+            };
+            if !numbers.contains(&number) {
+                numbers.push(number);
+            }
+        }
+        numbers
+    }
+}
+
+const BGM_COLUMNS: &str =
+    "id, name, chinese, start_date, weekday, clock, episode, episode_count, regex, path, state, sources, grace_hours, upgrade_hours, episode_offset, numbering, accept";
+
+fn sources_to_sql(sources: &[String]) -> String {
+    if sources.is_empty() {
+        String::new()
+    } else {
+        serde_json::to_string(sources).unwrap_or_default()
+    }
+}
+
+fn accept_to_sql(accept: &[Numbering]) -> String {
+    if accept.is_empty() {
+        String::new()
+    } else {
+        serde_json::to_string(accept).unwrap_or_default()
+    }
+}
+
+fn bgm_from_row(row: &Row) -> rusqlite::Result<Bgm> {
+    Ok(Bgm {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        chinese: row.get(2).unwrap_or_default(),
+        start_date: row.get(3).unwrap_or_default(),
+        weekday: row.get(4)?,
+        clock: row.get(5).unwrap_or_default(),
+        episode: row.get(6).unwrap_or_default(),
+        episode_count: row.get(7).unwrap_or_default(),
+        regex: row.get(8)?,
+        path: row.get(9).unwrap_or(config().download.path.clone()),
+        state: row.get(10)?,
+        sources: {
+            let sources: String = row.get(11).unwrap_or_default();
+            if sources.is_empty() {
+                Vec::new()
+            } else {
+                serde_json::from_str(&sources).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(11, Type::Text, Box::new(e))
+                })?
+            }
+        },
+        grace_hours: row.get(12).unwrap_or_default(),
+        upgrade_hours: row.get(13).unwrap_or_default(),
+        offset: row.get(14).unwrap_or_default(),
+        numbering: {
+            let numbering: String = row.get(15)?;
+            Numbering::parse(&numbering).ok_or_else(|| {
+                rusqlite::Error::FromSqlConversionFailure(15, Type::Text, numbering.into())
+            })?
+        },
+        accept: {
+            let accept: String = row.get(16).unwrap_or_default();
+            if accept.is_empty() {
+                Vec::new()
+            } else {
+                serde_json::from_str(&accept).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(16, Type::Text, Box::new(e))
+                })?
+            }
+        },
+    })
+}
+
+pub fn get_new_bgms() -> Result<Vec<Bgm>> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(&format!("SELECT {BGM_COLUMNS} FROM bgm WHERE state = ?"))?;
+    let bgms = stmt.query_map([BgmState::New], bgm_from_row)?;
+    Ok(collect_rows(bgms, "bgm"))
+}
+
+pub fn get_bgms() -> Result<Vec<Bgm>> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(&format!("SELECT {BGM_COLUMNS} FROM bgm ORDER BY id"))?;
+    let bgms = stmt.query_map([], bgm_from_row)?;
+    Ok(collect_rows(bgms, "bgm"))
+}
+
+pub fn get_bgm(id: u32) -> Result<Option<Bgm>> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(&format!("SELECT {BGM_COLUMNS} FROM bgm WHERE id = ?"))?;
+    Ok(stmt.query_row([id], bgm_from_row).optional()?)
+}
+
+/// Inserts `bgm` and returns its id. An empty `path` is stored as NULL so the
+/// configured download path applies.
+pub fn add_bgm(bgm: &Bgm) -> Result<u32> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(
+        "INSERT INTO bgm(name, chinese, start_date, weekday, clock, episode, episode_count, regex, path, state, sources, grace_hours, upgrade_hours, episode_offset, numbering, accept)
+            VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, NULLIF(?9, ''), ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+    )?;
+    let id = stmt.insert(rusqlite::params![
+        bgm.name,
+        bgm.chinese,
+        bgm.start_date,
+        bgm.weekday,
+        bgm.clock,
+        bgm.episode,
+        bgm.episode_count,
+        bgm.regex,
+        bgm.path,
+        bgm.state,
+        sources_to_sql(&bgm.sources),
+        bgm.grace_hours,
+        bgm.upgrade_hours,
+        bgm.offset,
+        bgm.numbering.as_str(),
+        accept_to_sql(&bgm.accept)
+    ])?;
+    Ok(id as u32)
+}
+
+pub fn update_bgm(bgm: &Bgm) -> Result<()> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(
+        "UPDATE bgm SET name = ?1, chinese = ?2, start_date = ?3, weekday = ?4, clock = ?5, episode = ?6,
+            episode_count = ?7, regex = ?8, state = ?10, sources = ?13, grace_hours = ?14, upgrade_hours = ?15,
+            episode_offset = ?16, numbering = ?17, accept = ?18,
+            path = CASE WHEN path IS NULL AND ?9 = ?12 THEN NULL ELSE NULLIF(?9, '') END
+            WHERE id = ?11",
+    )?;
+    stmt.execute(rusqlite::params![
+        bgm.name,
+        bgm.chinese,
+        bgm.start_date,
+        bgm.weekday,
+        bgm.clock,
+        bgm.episode,
+        bgm.episode_count,
+        bgm.regex,
+        bgm.path,
+        bgm.state,
+        bgm.id,
+        config().download.path,
+        sources_to_sql(&bgm.sources),
+        bgm.grace_hours,
+        bgm.upgrade_hours,
+        bgm.offset,
+        bgm.numbering.as_str(),
+        accept_to_sql(&bgm.accept)
+    ])?;
+    Ok(())
+}
+
+/// Deletes the bgm together with its tasks, torrents and history, returns
+/// false when there was no such bgm.
+pub fn remove_bgm(id: u32) -> Result<bool> {
+    let mut ctx = db();
+    let tx = ctx.transaction()?;
+    tx.execute("DELETE FROM task WHERE bgm_id = ?", [id])?;
+    tx.execute("DELETE FROM torrent WHERE bgm_id = ?", [id])?;
+    tx.execute("DELETE FROM history WHERE bgm_id = ?", [id])?;
+    let n = tx.execute("DELETE FROM bgm WHERE id = ?", [id])?;
+    tx.commit()?;
+    Ok(n > 0)
+}
+
+pub fn update_bgm_state(bgm: Bgm) -> Result<()> {
+    let ctx = db();
     let mut stmt = ctx.prepare("UPDATE bgm SET state = ? WHERE id = ?")?;
 
     stmt.execute(rusqlite::params![bgm.state, bgm.id])?;
@@ -0,0 +1,475 @@
+use crate::bgminfo::{self, Bgm, Numbering};
+use crate::config::{self, Overrides};
+use crate::db;
+use crate::error::{Error, Result};
+use crate::history;
+use crate::source;
+use crate::state::{BgmState, TaskState, Transition};
+use crate::task;
+use crate::taskinfo;
+use crate::title::Special;
+use chrono::{Local, NaiveDate};
+use clap::{Args, Parser, Subcommand};
+
+/// Downloads bangumi episodes as they air
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub overrides: Overrides,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the download daemon (the default)
+    Run,
+    /// Subscribe to a bangumi
+    Add(AddArgs),
+    /// Change a subscription
+    Edit(EditArgs),
+    /// Remove a subscription together with its tasks
+    Rm { id: u32 },
+    /// List subscriptions
+    List,
+    /// List tasks
+    Tasks {
+        /// only tasks of this bgm
+        #[arg(long)]
+        bgm: Option<u32>,
+        /// only tasks in these states
+        #[arg(long, value_delimiter = ',')]
+        state: Vec<TaskState>,
+    },
+    /// Reset a finished or failed task so it is searched for again
+    Retry {
+        id: u32,
+        /// let the releases sent for the task before be sent again
+        #[arg(long)]
+        force: bool,
+    },
+    /// Add a special episode of a bgm as a task
+    Special {
+        bgm: u32,
+        /// sp[N], ova[N], recap or a fractional episode like 12.5
+        #[arg(value_parser = parse_special)]
+        special: Special,
+        /// air date, YYYYMMDD, today when unset
+        #[arg(long, value_parser = parse_date)]
+        date: Option<String>,
+    },
+    /// List the releases seen for a bgm and when they were sent
+    History {
+        bgm: u32,
+        /// list what happened to its tasks instead
+        #[arg(long)]
+        events: bool,
+    },
+}
+
+#[derive(Args)]
+pub struct AddArgs {
+    /// name used in log output
+    name: String,
+    /// title pattern, episode numbers are matched after it
+    #[arg(long)]
+    regex: String,
+    /// date of the first episode, YYYYMMDD
+    #[arg(long, value_parser = parse_date)]
+    start_date: String,
+    /// air day, 1 = Monday .. 7 = Sunday
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=7))]
+    weekday: u8,
+    /// air hour, 0..=23
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=23))]
+    clock: u8,
+    /// first episode to download
+    #[arg(long, default_value_t = 1)]
+    episode: u32,
+    /// number of episodes to download
+    #[arg(long)]
+    count: u32,
+    #[arg(long, default_value = "")]
+    chinese: String,
+    /// download directory, defaults to download.path of the config
+    #[arg(long, default_value = "")]
+    path: String,
+    /// torrent source, repeat for several, defaults to source.default of the
+    /// config: moe, moe:<keywords>, moe:tag=<tag id>[,<tag id>], nyaa,
+    /// nyaa:<search terms>, mikan (the token feed),
+    /// mikan:<bangumi id>[/<subgroup id>], mikan:<feed url>, dmhy:<keyword> or
+    /// dmhy:keyword=<keyword>&team_id=<team>, rss:<url> or feed:<name of a
+    /// [feeds] table>
+    #[arg(long = "source")]
+    sources: Vec<String>,
+    /// hours to wait for a release of a score.groups group before taking the
+    /// best one found
+    #[arg(long, default_value_t = 0)]
+    grace: u32,
+    /// hours after airing a downloaded episode is replaced by a v2 or a
+    /// better scored release
+    #[arg(long, default_value_t = 0)]
+    upgrade: u32,
+    /// absolute numbers are the season's plus this, e.g. 12 for a second
+    /// cour some groups release as 13-24
+    #[arg(long, default_value_t = 0)]
+    offset: u32,
+    /// numbering of the tasks, --episode and --count count per season
+    #[arg(long, value_enum, default_value_t = Numbering::Season)]
+    numbering: Numbering,
+    /// numberings releases may use, both by default
+    #[arg(long, value_enum, value_delimiter = ',')]
+    accept: Vec<Numbering>,
+}
+
+#[derive(Args)]
+pub struct EditArgs {
+    id: u32,
+    #[arg(long)]
+    name: Option<String>,
+    #[arg(long)]
+    regex: Option<String>,
+    #[arg(long, value_parser = parse_date)]
+    start_date: Option<String>,
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=7))]
+    weekday: Option<u8>,
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=23))]
+    clock: Option<u8>,
+    #[arg(long)]
+    episode: Option<u32>,
+    #[arg(long)]
+    count: Option<u32>,
+    #[arg(long)]
+    chinese: Option<String>,
+    #[arg(long)]
+    path: Option<String>,
+    /// replaces the torrent sources, `default` for source.default of the
+    /// config
+    #[arg(long = "source")]
+    sources: Vec<String>,
+    /// hours to wait for a preferred group, 0 to stop waiting
+    #[arg(long)]
+    grace: Option<u32>,
+    /// hours after airing to look for better releases, 0 to stop looking
+    #[arg(long)]
+    upgrade: Option<u32>,
+    /// renumbers the tasks not sent yet
+    #[arg(long)]
+    offset: Option<u32>,
+    /// renumbers the tasks not sent yet
+    #[arg(long, value_enum)]
+    numbering: Option<Numbering>,
+    /// replaces the numberings releases may use
+    #[arg(long, value_enum, value_delimiter = ',')]
+    accept: Vec<Numbering>,
+}
+
+fn parse_date(s: &str) -> std::result::Result<String, String> {
+    NaiveDate::parse_from_str(s, "%Y%m%d")
+        .map(|_| s.to_string())
+        .map_err(|e| format!("expect YYYYMMDD: {e}"))
+}
+
+fn parse_special(s: &str) -> std::result::Result<Special, String> {
+    s.parse().map_err(|e: Error| e.to_string())
+}
+
+/// Checks `specs` against the loaded config, `default` standing for
+/// source.default.
+fn parse_sources(specs: Vec<String>) -> Result<Vec<String>> {
+    let specs: Vec<String> = specs.into_iter().filter(|s| s != "default").collect();
+    for spec in &specs {
+        source::parse(spec)?;
+    }
+    Ok(specs)
+}
+
+pub async fn run(cli: Cli) -> Result<()> {
+    config::init_config(&cli.overrides)?;
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => task::exec().await,
+        Command::Add(args) => db::init_db().and_then(|_| add(args)),
+        Command::Edit(args) => db::init_db().and_then(|_| edit(args)),
+        Command::Rm { id } => db::init_db().and_then(|_| rm(id)),
+        Command::List => db::init_db().and_then(|_| list()),
+        Command::Tasks { bgm, state } => db::init_db().and_then(|_| tasks(bgm, &state)),
+        Command::Retry { id, force } => db::init_db().and_then(|_| retry(id, force)),
+        Command::Special { bgm, special, date } => {
+            db::init_db().and_then(|_| add_special(bgm, special, date))
+        }
+        Command::History { bgm, events } => db::init_db().and_then(|_| history(bgm, events)),
+    }
+}
+
+fn add(args: AddArgs) -> Result<()> {
+    let bgm = Bgm {
+        id: 0,
+        name: args.name,
+        chinese: args.chinese,
+        start_date: args.start_date,
+        weekday: args.weekday,
+        clock: args.clock,
+        episode: args.episode,
+        episode_count: args.count,
+        regex: args.regex,
+        path: args.path,
+        state: BgmState::New,
+        sources: parse_sources(args.sources)?,
+        grace_hours: args.grace,
+        upgrade_hours: args.upgrade,
+        offset: args.offset,
+        numbering: args.numbering,
+        accept: args.accept,
+    };
+    let id = bgminfo::add_bgm(&bgm)?;
+    println!("added bgm:{id} {}", bgm.name);
+    Ok(())
+}
+
+fn edit(args: EditArgs) -> Result<()> {
+    let mut bgm =
+        bgminfo::get_bgm(args.id)?.ok_or_else(|| Error::NotFound(format!("bgm:{}", args.id)))?;
+    let old = bgm.clone();
+    let rematch = args.regex.is_some()
+        || args.path.is_some()
+        || args.offset.is_some()
+        || args.numbering.is_some()
+        || !args.accept.is_empty();
+    let reschedule = args.start_date.is_some()
+        || args.weekday.is_some()
+        || args.clock.is_some()
+        || args.episode.is_some()
+        || args.count.is_some();
+    if reschedule && bgm.state == BgmState::Scheduled {
+        return Err(Error::State(format!(
+            "tasks of bgm:{} are already scheduled, --start-date, --weekday, --clock, --episode and --count only apply before",
+            bgm.id
+        )));
+    }
+
+    bgm.name = args.name.unwrap_or(bgm.name);
+    bgm.chinese = args.chinese.unwrap_or(bgm.chinese);
+    bgm.start_date = args.start_date.unwrap_or(bgm.start_date);
+    bgm.weekday = args.weekday.unwrap_or(bgm.weekday);
+    bgm.clock = args.clock.unwrap_or(bgm.clock);
+    bgm.episode = args.episode.unwrap_or(bgm.episode);
+    bgm.episode_count = args.count.unwrap_or(bgm.episode_count);
+    bgm.regex = args.regex.unwrap_or(bgm.regex);
+    bgm.path = args.path.unwrap_or(bgm.path);
+    bgm.grace_hours = args.grace.unwrap_or(bgm.grace_hours);
+    bgm.upgrade_hours = args.upgrade.unwrap_or(bgm.upgrade_hours);
+    bgm.offset = args.offset.unwrap_or(bgm.offset);
+    bgm.numbering = args.numbering.unwrap_or(bgm.numbering);
+    if !args.accept.is_empty() {
+        bgm.accept = args.accept;
+    }
+    if !args.sources.is_empty() {
+        bgm.sources = parse_sources(args.sources)?;
+    }
+    if bgm.state == BgmState::Invalid {
+        // give the fixed schedule another go
+        bgm.state
+            .transition(BgmState::New, format!("bgm:{}", bgm.id));
+    }
+
+    bgminfo::update_bgm(&bgm)?;
+    if rematch {
+        let path = bgminfo::get_bgm(bgm.id)?.map_or_else(|| bgm.path.clone(), |b| b.path);
+        taskinfo::update_unsent_tasks(&old, &bgm, &path)?;
+    }
+    println!("updated bgm:{} {}", bgm.id, bgm.name);
+    Ok(())
+}
+
+fn rm(id: u32) -> Result<()> {
+    if !bgminfo::remove_bgm(id)? {
+        return Err(Error::NotFound(format!("bgm:{id}")));
+    }
+    println!("removed bgm:{id}");
+    Ok(())
+}
+
+fn list() -> Result<()> {
+    println!(
+        "{:>4}  {:<9}  {:>3}  {:>5}  {:>7}  {:<8}  {:<24}  {:<24}  {:<12}  {:>5}  {:>7}  {:<12}  path",
+        "id",
+        "state",
+        "day",
+        "clock",
+        "episode",
+        "start",
+        "name",
+        "regex",
+        "sources",
+        "grace",
+        "upgrade",
+        "numbering"
+    );
+    for bgm in bgminfo::get_bgms()? {
+        let sources = bgm.sources().join(",");
+        let mut numbering = bgm.numbering.to_string();
+        if bgm.offset > 0 {
+            numbering += &format!("+{}", bgm.offset);
+        }
+        if !bgm.accept.is_empty() {
+            let accept: Vec<String> = bgm.accept.iter().map(|n| n.to_string()).collect();
+            numbering += &format!(" ({})", accept.join(","));
+        }
+        let name = if bgm.chinese.is_empty() {
+            bgm.name
+        } else {
+            format!("{} ({})", bgm.name, bgm.chinese)
+        };
+        println!(
+            "{:>4}  {:<9}  {:>3}  {:>5}  {:>7}  {:<8}  {:<24}  {:<24}  {:<12}  {:>5}  {:>7}  {:<12}  {}",
+            bgm.id,
+            bgm.state,
+            bgm.weekday,
+            bgm.clock,
+            format!("{}+{}", bgm.episode, bgm.episode_count),
+            bgm.start_date,
+            name,
+            bgm.regex,
+            sources,
+            format!("{}h", bgm.grace_hours),
+            format!("{}h", bgm.upgrade_hours),
+            numbering,
+            bgm.path
+        );
+    }
+    Ok(())
+}
+
+fn tasks(bgm: Option<u32>, states: &[TaskState]) -> Result<()> {
+    println!(
+        "{:>5}  {:>4}  {:>4}  {:<11}  {:<19}  {:<19}  regex",
+        "id", "bgm", "ep", "state", "exec_time", "finish_time"
+    );
+    for task in taskinfo::get_tasks(bgm, states)? {
+        println!(
+            "{:>5}  {:>4}  {:>4}  {:<11}  {:<19}  {:<19}  {}",
+            task.id,
+            task.bgm_id,
+            task.special
+                .map_or_else(|| task.episode.to_string(), |s| s.to_string()),
+            task.state,
+            task.exec_time,
+            task.finish_time,
+            task.regex
+        );
+    }
+    Ok(())
+}
+
+fn add_special(bgm_id: u32, special: Special, date: Option<String>) -> Result<()> {
+    let bgm = bgminfo::get_bgm(bgm_id)?.ok_or_else(|| Error::NotFound(format!("bgm:{bgm_id}")))?;
+    if let Some(task) = taskinfo::get_tasks(Some(bgm_id), &[])?
+        .into_iter()
+        .find(|t| t.special == Some(special))
+    {
+        return Err(Error::State(format!(
+            "{special} of bgm:{bgm_id} is task:{} already",
+            task.id
+        )));
+    }
+    let now = Local::now();
+    let exec_time = match date {
+        Some(date) => NaiveDate::parse_from_str(&date, "%Y%m%d")?
+            .and_hms_opt(bgm.clock as u32, 0, 0)
+            .ok_or_else(|| Error::parse(format!("clock {} of bgm:{bgm_id}", bgm.clock)))?,
+        None => now.naive_local(),
+    };
+    taskinfo::generate_tasks(&vec![taskinfo::Task {
+        id: 0,
+        bgm_id,
+        episode: special.episode(),
+        regex: taskinfo::special_regex(&bgm.regex),
+        path: bgm.path,
+        uri: String::new(),
+        gid: String::new(),
+        exec_time: exec_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        create_time: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+        finish_time: String::new(),
+        state: TaskState::Pending,
+        grace_deadline: String::new(),
+        title: String::new(),
+        files: Vec::new(),
+        replaces: Vec::new(),
+        special: Some(special),
+    }])?;
+    println!("added {special} of bgm:{bgm_id} {}", bgm.name);
+    Ok(())
+}
+
+fn retry(id: u32, force: bool) -> Result<()> {
+    let mut task = taskinfo::get_task(id)?.ok_or_else(|| Error::NotFound(format!("task:{id}")))?;
+    if !task
+        .state
+        .transition(TaskState::Pending, format!("task:{id}"))
+    {
+        return Err(Error::State(format!(
+            "task:{id} is {}, only done or bad-regex tasks can be retried",
+            task.state
+        )));
+    }
+    task.uri.clear();
+    task.gid.clear();
+    task.finish_time.clear();
+    task.grace_deadline.clear();
+    task.title.clear();
+    task.files.clear();
+    task.replaces.clear();
+    taskinfo::update_task(&task)?;
+    if force {
+        let n = history::unsend_task(id)?;
+        println!("{n} releases sent for task:{id} may be sent again");
+    }
+    println!("task:{id} will be searched for again");
+    Ok(())
+}
+
+fn history(bgm: u32, events: bool) -> Result<()> {
+    if events {
+        println!(
+            "{:<19}  {:>5}  {:>3}  {:<8}  title",
+            "time", "task", "ep", "event"
+        );
+        for h in history::get_history(bgm)? {
+            let title = if h.replaced.is_empty() {
+                h.title
+            } else {
+                format!("{} (was {})", h.title, h.replaced)
+            };
+            println!(
+                "{:<19}  {:>5}  {:>3}  {:<8}  {}",
+                h.time,
+                h.task_id,
+                h.episode,
+                h.event.as_str(),
+                title
+            );
+        }
+        return Ok(());
+    }
+    println!(
+        "{:<19}  {:<19}  {:<19}  {:>8}  {:<40}  title",
+        "published", "seen", "sent", "size", "info_hash"
+    );
+    for t in history::get_torrents(bgm)? {
+        println!(
+            "{:<19}  {:<19}  {:<19}  {:>8}  {:<40}  {}",
+            t.publish_time,
+            t.seen_time,
+            if t.sent_time.is_empty() {
+                "-"
+            } else {
+                &t.sent_time
+            },
+            format!("{}MiB", t.size >> 20),
+            t.info_hash,
+            t.title
+        );
+    }
+    Ok(())
+}
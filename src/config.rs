@@ -0,0 +1,679 @@
+use crate::error::{Error, Result};
+use crate::source;
+use crate::title::{Codec, Language};
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+const DEFAULT_CONFIG_FILE: &str = "bgm.toml";
+const DEFAULT_UA: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/109.0.0.0 Safari/537.36";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub db: DbConfig,
+    pub log: LogConfig,
+    pub download: DownloadConfig,
+    pub aria2: Aria2Config,
+    pub qbittorrent: QbittorrentConfig,
+    pub transmission: TransmissionConfig,
+    pub watch: WatchConfig,
+    /// read from `[moe]` by configs written before it applied to every site
+    #[serde(alias = "moe")]
+    pub http: HttpConfig,
+    pub nyaa: NyaaConfig,
+    pub mikan: MikanConfig,
+    pub dmhy: DmhyConfig,
+    pub source: SourceConfig,
+    pub score: ScoreConfig,
+    /// feeds read by the `feed:<name>` source, keyed by name
+    pub feeds: HashMap<String, FeedConfig>,
+    pub supervisor: SupervisorConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DbConfig {
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub dir: PathBuf,
+    pub file: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DownloadConfig {
+    /// used when a bgm row has no path of its own
+    pub path: String,
+    /// client the matched torrents are handed to
+    pub client: DownloaderKind,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloaderKind {
+    #[default]
+    Aria2,
+    Qbittorrent,
+    Transmission,
+    /// a client watching a folder for .magnet and .torrent files
+    Watch,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Aria2Config {
+    pub url: String,
+    /// `--rpc-secret` of aria2
+    pub secret: Option<String>,
+    /// websocket endpoint for notifications, derived from `url` when unset
+    pub ws_url: Option<String>,
+    /// interval of polling downloads in case a notification got lost, in
+    /// seconds
+    pub reconcile_secs: u64,
+}
+
+impl Aria2Config {
+    /// `ws_url`, or `url` with its scheme switched to ws/wss.
+    pub fn ws_url(&self) -> String {
+        if let Some(url) = &self.ws_url {
+            return url.clone();
+        }
+        match self.url.split_once("://") {
+            Some(("https", rest)) => format!("wss://{rest}"),
+            Some((_, rest)) => format!("ws://{rest}"),
+            None => self.url.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QbittorrentConfig {
+    /// address of the WebUI
+    pub url: String,
+    pub username: String,
+    /// not needed when the WebUI skips authentication for us
+    pub password: Option<String>,
+    /// interval of polling downloads, in seconds
+    pub poll_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransmissionConfig {
+    /// rpc endpoint
+    pub url: String,
+    /// only needed with rpc-authentication-required
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// interval of polling downloads, in seconds
+    pub poll_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// folder the client picks new downloads up from
+    pub dir: PathBuf,
+    /// interval of looking for finished files, in seconds
+    pub poll_secs: u64,
+    /// how deep finished files are searched for below `task.path`
+    pub depth: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// proxy of the requests to every torrent site, none by default
+    pub proxy: Option<String>,
+    pub user_agent: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NyaaConfig {
+    pub url: String,
+    /// e.g. 1_0 for all anime, 1_2 for English-translated
+    pub category: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MikanConfig {
+    pub url: String,
+    /// token of the personal `MyBangumi` feed, read by the `mikan` source
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DmhyConfig {
+    /// the rss endpoint
+    pub url: String,
+}
+
+/// An RSS or Atom feed with the paths its fields live at, like `title` or
+/// `enclosure/@url`. Unset paths fall back to RSS 2.0 and Atom.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeedConfig {
+    pub url: String,
+    /// path of the items from the root element, e.g. `channel/item`
+    pub item: Option<String>,
+    pub title: Option<String>,
+    /// a magnet or a .torrent url
+    pub link: Option<String>,
+    pub info_hash: Option<String>,
+    pub pub_date: Option<String>,
+    pub size: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceConfig {
+    /// sources of the bgm rows that don't pick their own
+    pub default: Vec<String>,
+}
+
+/// How the releases matching a task are ranked. Lists go from the most
+/// preferred down, anything unlisted comes last.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoreConfig {
+    /// fansub groups, a group matches when it contains the entry, any case
+    pub groups: Vec<String>,
+    /// vertical lines, e.g. 1080
+    pub resolutions: Vec<u16>,
+    pub subtitles: Vec<Language>,
+    pub codecs: Vec<Codec>,
+    /// size bounds like `200MiB` or `4GB`, for releases of known size
+    pub min_size: Option<String>,
+    pub max_size: Option<String>,
+    /// for releases of known seeders
+    pub min_seeders: u32,
+}
+
+impl ScoreConfig {
+    pub fn min_size(&self) -> Option<u64> {
+        self.min_size.as_deref().and_then(source::parse_size)
+    }
+
+    pub fn max_size(&self) -> Option<u64> {
+        self.max_size.as_deref().and_then(source::parse_size)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SupervisorConfig {
+    /// first restart delay in seconds, doubled on every crash
+    pub backoff_min: u64,
+    pub backoff_max: u64,
+    /// how long startup waits for the health checks to pass, in seconds
+    pub startup_timeout: u64,
+    /// health checks keyed by `procs.name`
+    pub health: HashMap<String, HealthCheck>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthCheck {
+    /// `host:port` that must accept tcp connections
+    pub addr: String,
+    #[serde(default = "HealthCheck::default_interval")]
+    pub interval: u64,
+    /// consecutive failures before the process is restarted
+    #[serde(default = "HealthCheck::default_retries")]
+    pub retries: u32,
+}
+
+impl HealthCheck {
+    fn default_interval() -> u64 {
+        10
+    }
+
+    fn default_retries() -> u32 {
+        3
+    }
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        DbConfig {
+            file: PathBuf::from("bgm.db"),
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            dir: PathBuf::from("."),
+            file: "bgm.log".to_string(),
+        }
+    }
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        DownloadConfig {
+            path: "download".to_string(),
+            client: DownloaderKind::default(),
+        }
+    }
+}
+
+impl Default for Aria2Config {
+    fn default() -> Self {
+        Aria2Config {
+            url: "http://localhost:6800/jsonrpc".to_string(),
+            secret: None,
+            ws_url: None,
+            reconcile_secs: 60,
+        }
+    }
+}
+
+impl Default for QbittorrentConfig {
+    fn default() -> Self {
+        QbittorrentConfig {
+            url: "http://localhost:8080".to_string(),
+            username: "admin".to_string(),
+            password: None,
+            poll_secs: 10,
+        }
+    }
+}
+
+impl Default for TransmissionConfig {
+    fn default() -> Self {
+        TransmissionConfig {
+            url: "http://localhost:9091/transmission/rpc".to_string(),
+            username: None,
+            password: None,
+            poll_secs: 10,
+        }
+    }
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            dir: PathBuf::new(),
+            poll_secs: 60,
+            depth: 2,
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            proxy: None,
+            user_agent: DEFAULT_UA.to_string(),
+        }
+    }
+}
+
+impl Default for NyaaConfig {
+    fn default() -> Self {
+        NyaaConfig {
+            url: "https://nyaa.si/".to_string(),
+            category: "1_0".to_string(),
+        }
+    }
+}
+
+impl Default for MikanConfig {
+    fn default() -> Self {
+        MikanConfig {
+            url: "https://mikanani.me/".to_string(),
+            token: None,
+        }
+    }
+}
+
+impl Default for DmhyConfig {
+    fn default() -> Self {
+        DmhyConfig {
+            url: "https://share.dmhy.org/topics/rss/rss.xml".to_string(),
+        }
+    }
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        SourceConfig {
+            default: vec!["moe".to_string()],
+        }
+    }
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        SupervisorConfig {
+            backoff_min: 1,
+            backoff_max: 300,
+            startup_timeout: 30,
+            health: HashMap::new(),
+        }
+    }
+}
+
+/// Overrides for the config file, taken from the command line or the
+/// matching `BGM_*` environment variable (command line wins).
+#[derive(Debug, Default, Args)]
+pub struct Overrides {
+    /// config file, defaults to ./bgm.toml when present
+    #[arg(short, long, env = "BGM_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+    /// sqlite database file
+    #[arg(long, env = "BGM_DB_FILE", global = true)]
+    pub db_file: Option<PathBuf>,
+    /// directory of bgm.log
+    #[arg(long, env = "BGM_LOG_DIR", global = true)]
+    pub log_dir: Option<PathBuf>,
+    /// download directory used when a bgm has no path
+    #[arg(long, env = "BGM_DOWNLOAD_PATH", global = true)]
+    pub download_path: Option<String>,
+    /// download client
+    #[arg(long, env = "BGM_DOWNLOADER", global = true)]
+    pub downloader: Option<DownloaderKind>,
+    /// aria2 json-rpc endpoint
+    #[arg(long, env = "BGM_ARIA2_URL", global = true)]
+    pub aria2_url: Option<String>,
+    /// aria2 --rpc-secret
+    #[arg(long, env = "BGM_ARIA2_SECRET", global = true, hide_env_values = true)]
+    pub aria2_secret: Option<String>,
+    /// qBittorrent WebUI address
+    #[arg(long, env = "BGM_QBITTORRENT_URL", global = true)]
+    pub qbittorrent_url: Option<String>,
+    /// qBittorrent WebUI user
+    #[arg(long, env = "BGM_QBITTORRENT_USERNAME", global = true)]
+    pub qbittorrent_username: Option<String>,
+    /// qBittorrent WebUI password
+    #[arg(
+        long,
+        env = "BGM_QBITTORRENT_PASSWORD",
+        global = true,
+        hide_env_values = true
+    )]
+    pub qbittorrent_password: Option<String>,
+    /// Transmission rpc endpoint
+    #[arg(long, env = "BGM_TRANSMISSION_URL", global = true)]
+    pub transmission_url: Option<String>,
+    /// Transmission rpc user
+    #[arg(long, env = "BGM_TRANSMISSION_USERNAME", global = true)]
+    pub transmission_username: Option<String>,
+    /// Transmission rpc password
+    #[arg(
+        long,
+        env = "BGM_TRANSMISSION_PASSWORD",
+        global = true,
+        hide_env_values = true
+    )]
+    pub transmission_password: Option<String>,
+    /// folder watched by the download client
+    #[arg(long, env = "BGM_WATCH_DIR", global = true)]
+    pub watch_dir: Option<PathBuf>,
+    /// token of the personal Mikan Project feed
+    #[arg(long, env = "BGM_MIKAN_TOKEN", global = true, hide_env_values = true)]
+    pub mikan_token: Option<String>,
+    /// proxy used to reach the torrent sites, empty to disable
+    #[arg(long, env = "BGM_HTTP_PROXY", global = true)]
+    pub proxy: Option<String>,
+    /// user agent used to reach the torrent sites
+    #[arg(long, env = "BGM_USER_AGENT", global = true)]
+    pub user_agent: Option<String>,
+}
+
+impl Config {
+    fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::config(format!("read {}: {}", path.display(), e)))?;
+        let cfg = toml::from_str(&text)
+            .map_err(|e| Error::config(format!("parse {}: {}", path.display(), e)))?;
+        Ok(cfg)
+    }
+
+    fn apply(&mut self, o: &Overrides) {
+        if let Some(file) = &o.db_file {
+            self.db.file = file.clone();
+        }
+        if let Some(dir) = &o.log_dir {
+            self.log.dir = dir.clone();
+        }
+        if let Some(path) = &o.download_path {
+            self.download.path = path.clone();
+        }
+        if let Some(client) = o.downloader {
+            self.download.client = client;
+        }
+        if let Some(url) = &o.aria2_url {
+            self.aria2.url = url.clone();
+        }
+        if let Some(secret) = &o.aria2_secret {
+            self.aria2.secret = Some(secret.clone());
+        }
+        if self.aria2.secret.as_ref().is_some_and(|s| s.is_empty()) {
+            self.aria2.secret = None;
+        }
+        if let Some(url) = &o.qbittorrent_url {
+            self.qbittorrent.url = url.clone();
+        }
+        if let Some(username) = &o.qbittorrent_username {
+            self.qbittorrent.username = username.clone();
+        }
+        if let Some(password) = &o.qbittorrent_password {
+            self.qbittorrent.password = Some(password.clone());
+        }
+        if let Some(url) = &o.transmission_url {
+            self.transmission.url = url.clone();
+        }
+        if let Some(username) = &o.transmission_username {
+            self.transmission.username = Some(username.clone());
+        }
+        if let Some(password) = &o.transmission_password {
+            self.transmission.password = Some(password.clone());
+        }
+        if self
+            .transmission
+            .username
+            .as_ref()
+            .is_some_and(|u| u.is_empty())
+        {
+            self.transmission.username = None;
+        }
+        if let Some(dir) = &o.watch_dir {
+            self.watch.dir = dir.clone();
+        }
+        if let Some(token) = &o.mikan_token {
+            self.mikan.token = Some(token.clone());
+        }
+        if self.mikan.token.as_ref().is_some_and(|t| t.is_empty()) {
+            self.mikan.token = None;
+        }
+        if let Some(proxy) = &o.proxy {
+            self.http.proxy = Some(proxy.clone());
+        }
+        if let Some(ua) = &o.user_agent {
+            self.http.user_agent = ua.clone();
+        }
+        if self.http.proxy.as_ref().is_some_and(|p| p.is_empty()) {
+            self.http.proxy = None;
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.db.file.as_os_str().is_empty() {
+            return Err(Error::config("db.file must not be empty"));
+        }
+        if self.log.file.is_empty() {
+            return Err(Error::config("log.file must not be empty"));
+        }
+        if self.download.path.is_empty() {
+            return Err(Error::config("download.path must not be empty"));
+        }
+        reqwest::Url::parse(&self.aria2.url).map_err(|e| {
+            Error::config(format!(
+                "aria2.url `{}` is not a valid url: {}",
+                self.aria2.url, e
+            ))
+        })?;
+        if let Some(url) = &self.aria2.ws_url {
+            reqwest::Url::parse(url)
+                .ok()
+                .filter(|u| matches!(u.scheme(), "ws" | "wss"))
+                .ok_or_else(|| {
+                    Error::config(format!(
+                        "aria2.ws_url `{}` is not a ws:// or wss:// url",
+                        url
+                    ))
+                })?;
+        }
+        if self.aria2.reconcile_secs == 0 {
+            return Err(Error::config("aria2.reconcile_secs must be positive"));
+        }
+        reqwest::Url::parse(&self.qbittorrent.url).map_err(|e| {
+            Error::config(format!(
+                "qbittorrent.url `{}` is not a valid url: {}",
+                self.qbittorrent.url, e
+            ))
+        })?;
+        if self.qbittorrent.poll_secs == 0 {
+            return Err(Error::config("qbittorrent.poll_secs must be positive"));
+        }
+        reqwest::Url::parse(&self.transmission.url).map_err(|e| {
+            Error::config(format!(
+                "transmission.url `{}` is not a valid url: {}",
+                self.transmission.url, e
+            ))
+        })?;
+        if self.transmission.poll_secs == 0 {
+            return Err(Error::config("transmission.poll_secs must be positive"));
+        }
+        if self.download.client == DownloaderKind::Watch && self.watch.dir.as_os_str().is_empty() {
+            return Err(Error::config("watch.dir is required by the watch client"));
+        }
+        if self.watch.poll_secs == 0 {
+            return Err(Error::config("watch.poll_secs must be positive"));
+        }
+        if let Some(proxy) = &self.http.proxy {
+            reqwest::Proxy::all(proxy).map_err(|e| {
+                Error::config(format!(
+                    "http.proxy `{}` is not a valid proxy: {}",
+                    proxy, e
+                ))
+            })?;
+        }
+        reqwest::Url::parse(&self.nyaa.url).map_err(|e| {
+            Error::config(format!(
+                "nyaa.url `{}` is not a valid url: {}",
+                self.nyaa.url, e
+            ))
+        })?;
+        reqwest::Url::parse(&self.mikan.url).map_err(|e| {
+            Error::config(format!(
+                "mikan.url `{}` is not a valid url: {}",
+                self.mikan.url, e
+            ))
+        })?;
+        reqwest::Url::parse(&self.dmhy.url).map_err(|e| {
+            Error::config(format!(
+                "dmhy.url `{}` is not a valid url: {}",
+                self.dmhy.url, e
+            ))
+        })?;
+        if self.source.default.is_empty() {
+            return Err(Error::config(
+                "source.default must name at least one source",
+            ));
+        }
+        for (name, feed) in &self.feeds {
+            reqwest::Url::parse(&feed.url).map_err(|e| {
+                Error::config(format!(
+                    "feeds.{name}.url `{}` is not a valid url: {}",
+                    feed.url, e
+                ))
+            })?;
+        }
+        for spec in &self.source.default {
+            source::parse_in(spec, &self.feeds)
+                .map_err(|e| Error::config(format!("source.default: {e}")))?;
+        }
+        for (key, size) in [
+            ("min_size", &self.score.min_size),
+            ("max_size", &self.score.max_size),
+        ] {
+            if let Some(size) = size.as_deref().filter(|s| source::parse_size(s).is_none()) {
+                return Err(Error::config(format!(
+                    "score.{key} `{size}` is not a size like 200MiB"
+                )));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.score.min_size(), self.score.max_size()) {
+            if min > max {
+                return Err(Error::config("score.min_size must not exceed max_size"));
+            }
+        }
+        if self.http.user_agent.is_empty() {
+            return Err(Error::config("http.user_agent must not be empty"));
+        }
+        let s = &self.supervisor;
+        if s.backoff_min == 0 || s.backoff_min > s.backoff_max {
+            return Err(Error::config(
+                "supervisor.backoff_min must be in 1..=backoff_max",
+            ));
+        }
+        for (name, check) in &s.health {
+            if check.addr.is_empty() {
+                return Err(Error::config(format!(
+                    "supervisor.health.{name}.addr must not be empty"
+                )));
+            }
+            if check.interval == 0 || check.retries == 0 {
+                return Err(Error::config(format!(
+                    "supervisor.health.{name}.interval and retries must be positive"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Builds the config from defaults, the config file and `o`, in increasing
+/// priority, and installs it for [`config`].
+pub fn init_config(o: &Overrides) -> Result<()> {
+    let mut cfg = match &o.config {
+        Some(path) => Config::from_file(path)?,
+        None => {
+            let path = PathBuf::from(DEFAULT_CONFIG_FILE);
+            if path.exists() {
+                Config::from_file(&path)?
+            } else {
+                Config::default()
+            }
+        }
+    };
+    cfg.apply(o);
+    cfg.validate()?;
+    CONFIG
+        .set(cfg)
+        .map_err(|_| Error::config("config is already initialized"))?;
+    Ok(())
+}
+
+pub fn config() -> &'static Config {
+    CONFIG.get().expect("config is not initialized")
+}
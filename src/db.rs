@@ -1,7 +1,22 @@
+use crate::config::config;
+use crate::error::{Error, Result};
 use rusqlite::{hooks::Action, Connection};
-use std::sync::{Mutex, OnceLock};
+use std::sync::{Mutex, MutexGuard, OnceLock};
 use tokio::sync::broadcast;
-const DB_FILE: &str = "I:/programs/bangumi/bgm.db";
+use tracing::{error, info};
+
+/// Schema migrations, `MIGRATIONS[n]` upgrades `user_version` n to n + 1.
+/// Only ever append to this list, released migrations must not change.
+const MIGRATIONS: &[&str] = &[
+    include_str!("migrations/0001_init.sql"),
+    include_str!("migrations/0002_bgm_sources.sql"),
+    include_str!("migrations/0003_grace_window.sql"),
+    include_str!("migrations/0004_upgrades.sql"),
+    include_str!("migrations/0005_torrents.sql"),
+    include_str!("migrations/0006_numbering.sql"),
+    include_str!("migrations/0007_specials.sql"),
+    include_str!("migrations/0008_torrent_per_bgm.sql"),
+];
 
 #[derive(Debug)]
 pub struct Db {
@@ -10,33 +25,76 @@ pub struct Db {
 }
 
 impl Db {
-    fn new() -> Self {
-        let ctx = Connection::open(DB_FILE).unwrap();
+    fn new() -> Result<Self> {
+        let mut ctx = Connection::open(&config().db.file)?;
+        migrate(&mut ctx)?;
         let (tx, _) = broadcast::channel(1);
-        Db {
+        Ok(Db {
             ctx: Mutex::new(ctx),
             tx,
-        }
+        })
+    }
+}
+
+fn migrate(ctx: &mut Connection) -> Result<()> {
+    let tx = ctx.transaction()?;
+    let version: usize = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(Error::config(format!(
+            "database schema version {} is newer than this build ({}), please upgrade",
+            version,
+            MIGRATIONS.len()
+        )));
+    }
+    for (idx, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        info!("migrate database schema to version {}", idx + 1);
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", idx + 1)?;
     }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Collects query rows, logging and skipping the ones that fail to convert
+/// so a single malformed row can't hold up the rest.
+pub fn collect_rows<T>(rows: impl Iterator<Item = rusqlite::Result<T>>, tbl: &str) -> Vec<T> {
+    rows.filter_map(|row| {
+        row.map_err(|e| error!("skip malformed {} row: {}", tbl, e))
+            .ok()
+    })
+    .collect()
 }
+
 static DB: OnceLock<Db> = OnceLock::new();
 
-pub fn init_db() {
-    DB.set(Db::new()).unwrap();
-    DB.get().unwrap().ctx.lock().unwrap().update_hook(Some(
-        |action, db: &str, tbl: &str, row_id| {
-            // println!("{:?} [{row_id}]/[{tbl}]@[{db}]", action,);
-            DB.get()
-                .unwrap()
-                .tx
-                .send((action, db.to_string(), tbl.to_string(), row_id))
-                .unwrap();
-        },
-    ));
-}
-
-pub fn db() -> &'static Mutex<Connection> {
-    &DB.get().unwrap().ctx
+pub fn init_db() -> Result<()> {
+    DB.set(Db::new()?)
+        .map_err(|_| Error::config("db is already initialized"))?;
+    db().update_hook(Some(|action, db: &str, tbl: &str, row_id| {
+        // println!("{:?} [{row_id}]/[{tbl}]@[{db}]", action,);
+        // nobody may be listening yet, e.g. while generating tasks at startup
+        let _ = DB
+            .get()
+            .unwrap()
+            .tx
+            .send((action, db.to_string(), tbl.to_string(), row_id));
+    }));
+    Ok(())
+}
+
+/// Locks the shared connection. A panic while it was held doesn't leave the
+/// connection in a bad state, so poisoning is ignored.
+pub fn db() -> MutexGuard<'static, Connection> {
+    DB.get()
+        .unwrap()
+        .ctx
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Moves on whenever another connection commits to the database.
+pub fn data_version() -> Result<i64> {
+    Ok(db().query_row("PRAGMA data_version", [], |row| row.get(0))?)
 }
 
 pub fn notify() -> &'static broadcast::Sender<(Action, String, String, i64)> {
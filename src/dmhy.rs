@@ -0,0 +1,100 @@
+use crate::config::config;
+use crate::downloader::magnet_info_hash;
+use crate::error::{Error, Result};
+use crate::source::{self, child_text, Release, TorrentSource};
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use tracing::error;
+
+#[cfg(test)]
+mod tests;
+
+/// The RSS feed of share.dmhy.org, narrowed by its query parameters.
+pub struct Dmhy {
+    /// `keyword`, `team_id` and `sort_id` pairs
+    query: Vec<(String, String)>,
+}
+
+impl Dmhy {
+    /// `arg` is either a plain keyword or query parameters like
+    /// `keyword=frieren&team_id=657`.
+    pub fn parse(arg: &str) -> Result<Self> {
+        if !arg.contains('=') {
+            return Ok(Dmhy {
+                query: vec![("keyword".to_string(), arg.to_string())],
+            });
+        }
+        let query = arg
+            .split('&')
+            .filter(|kv| !kv.is_empty())
+            .map(|kv| match kv.split_once('=') {
+                Some((k @ ("keyword" | "team_id" | "sort_id"), v)) => {
+                    Ok((k.to_string(), v.to_string()))
+                }
+                _ => Err(Error::parse(format!(
+                    "dmhy parameter `{kv}` is not one of keyword, team_id or sort_id"
+                ))),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Dmhy { query })
+    }
+
+    /// The feed at `base` with the query parameters.
+    fn url(&self, base: &str) -> Result<reqwest::Url> {
+        reqwest::Url::parse_with_params(base, &self.query).map_err(Error::parse)
+    }
+}
+
+fn parse_item(item: roxmltree::Node) -> Result<Release> {
+    let title = child_text(item, "title").ok_or_else(|| Error::parse("dmhy item without title"))?;
+    let magnet = item
+        .children()
+        .find(|c| c.has_tag_name("enclosure"))
+        .and_then(|c| c.attribute("url"))
+        .filter(|url| url.starts_with("magnet:"))
+        .ok_or_else(|| Error::parse(format!("dmhy item {title} without magnet")))?;
+    let info_hash = magnet_info_hash(magnet)
+        .ok_or_else(|| Error::parse(format!("no info hash in magnet of dmhy item {title}")))?;
+    let pub_date = child_text(item, "pubDate")
+        .ok_or_else(|| Error::parse(format!("dmhy item {title} without pubDate")))?;
+    Ok(Release {
+        title: title.to_string(),
+        magnet: magnet.to_string(),
+        info_hash,
+        size: 0,
+        publish_time: source::parse_pub_date(pub_date)?,
+        seeders: None,
+        files: Vec::new(),
+    })
+}
+
+/// Releases of a dmhy RSS document.
+pub fn parse_feed(xml: &str) -> Result<Vec<Release>> {
+    let doc = roxmltree::Document::parse(xml).map_err(Error::parse)?;
+    Ok(doc
+        .descendants()
+        .filter(|n| n.has_tag_name("item"))
+        .filter_map(|item| {
+            parse_item(item)
+                .map_err(|e| error!("skip dmhy item: {}", e))
+                .ok()
+        })
+        .collect())
+}
+
+#[async_trait]
+impl TorrentSource for Dmhy {
+    /// The feed carries the latest matches only, older ones are out of reach.
+    async fn releases(&self, _since: &NaiveDateTime) -> Result<Vec<Release>> {
+        let xml = source::client()?
+            .get(self.url(&config().dmhy.url)?)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(Error::Source)?
+            .text()
+            .await
+            .map_err(Error::Source)?;
+        parse_feed(&xml)
+    }
+}
@@ -0,0 +1,55 @@
+use super::*;
+use chrono::{DateTime, Local};
+
+fn local(rfc2822: &str) -> NaiveDateTime {
+    DateTime::parse_from_rfc2822(rfc2822)
+        .unwrap()
+        .with_timezone(&Local)
+        .naive_local()
+}
+
+const BASE: &str = "https://share.dmhy.org/topics/rss/rss.xml";
+
+#[test]
+fn feed() {
+    let releases = parse_feed(include_str!("feed.xml")).unwrap();
+    // no magnet, a short hash and no pubDate are skipped
+    assert_eq!(releases.len(), 2);
+
+    let r = &releases[0];
+    assert_eq!(
+        r.title,
+        "[桜都字幕组] 葬送的芙莉莲 / Sousou no Frieren [04][1080p][简繁内封]"
+    );
+    // the base32 hash of the magnet in hex
+    assert_eq!(r.info_hash, "0c0c35c6bdce0ada041ede0e618bcf67131e15c7");
+    assert!(r
+        .magnet
+        .starts_with("magnet:?xt=urn:btih:BQGDLRV5ZYFNUBA63YHGDC6PM4JR4FOH&dn=&tr="));
+    // the enclosure length is always 1, the size is unknown
+    assert_eq!(r.size, 0);
+    assert_eq!(r.publish_time, local("Fri, 13 Oct 2023 22:05:11 +0800"));
+
+    let r = &releases[1];
+    assert_eq!(r.info_hash, "0a1b2c3d4e5f60718293a4b5c6d7e8f901234567");
+    assert_eq!(r.publish_time, local("Thu, 15 Feb 2024 21:30:00 +0800"));
+}
+
+#[test]
+fn queries() {
+    let url = |arg: &str| Dmhy::parse(arg).unwrap().url(BASE).unwrap().to_string();
+    assert_eq!(
+        url("葬送的芙莉莲"),
+        format!("{BASE}?keyword=%E8%91%AC%E9%80%81%E7%9A%84%E8%8A%99%E8%8E%89%E8%8E%B2")
+    );
+    assert_eq!(
+        url("frieren 1080p"),
+        format!("{BASE}?keyword=frieren+1080p")
+    );
+    assert_eq!(
+        url("keyword=frieren&team_id=657&sort_id=2"),
+        format!("{BASE}?keyword=frieren&team_id=657&sort_id=2")
+    );
+    assert_eq!(url("team_id=657&"), format!("{BASE}?team_id=657"));
+    assert!(Dmhy::parse("keyword=frieren&order=date").is_err());
+}
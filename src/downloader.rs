@@ -0,0 +1,171 @@
+use crate::aria2::Aria2;
+use crate::config::{config, DownloaderKind};
+use crate::error::Result;
+use crate::qbittorrent::QBittorrent;
+use crate::transmission::Transmission;
+use crate::watch::WatchFolder;
+use async_trait::async_trait;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadState {
+    Active,
+    /// queued by the client, not started yet
+    Waiting,
+    Paused,
+    Complete,
+    Failed(String),
+    /// gone from the client, removed by a user or another program
+    Removed,
+}
+
+/// A download as seen by the client, whatever the backend.
+#[derive(Debug, Clone)]
+pub struct DownloadStatus {
+    pub id: String,
+    pub state: DownloadState,
+    pub total: u64,
+    pub completed: u64,
+    pub info_hash: Option<String>,
+    /// id of the download that took over this one, e.g. the torrent download
+    /// started by a magnet once its metadata arrived
+    pub followed_by: Option<String>,
+}
+
+impl DownloadStatus {
+    /// Completed, or done with the payload and merely seeding.
+    pub fn is_finished(&self) -> bool {
+        self.state == DownloadState::Complete || (self.total > 0 && self.completed == self.total)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadFile {
+    /// position of the file in the file list of the torrent, from 0, however
+    /// the client counts them
+    pub index: u32,
+    pub path: String,
+    pub length: u64,
+    pub completed: u64,
+    pub selected: bool,
+}
+
+/// Events pushed by clients able to notify about finished downloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Complete,
+    /// the payload of a torrent is done, the client keeps seeding it
+    BtComplete,
+    Error,
+    /// removed by a user or another program
+    Stop,
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub event: Event,
+    pub id: String,
+}
+
+/// A download client the scheduler hands matched torrents to. Downloads are
+/// identified by an id of the client's choosing, stored in `task.gid`.
+#[async_trait]
+pub trait Downloader: Send + Sync {
+    /// Starts downloading `uri` into `dir` and returns the id of the download.
+    /// `name` is the file or folder the download is expected to produce, or
+    /// the title of the release, when known.
+    async fn add(&self, uri: &str, dir: &str, name: Option<&str>) -> Result<String>;
+
+    /// Like `add`, downloading only the files of `indexes`, as in
+    /// `DownloadFile::index`, or all of them when empty. Clients
+    /// unable to pick files before the metadata arrives download them all,
+    /// until `select` is called.
+    async fn add_files(
+        &self,
+        uri: &str,
+        dir: &str,
+        name: Option<&str>,
+        _indexes: &[u32],
+    ) -> Result<String> {
+        self.add(uri, dir, name).await
+    }
+
+    async fn status(&self, id: &str) -> Result<DownloadStatus>;
+
+    async fn pause(&self, id: &str) -> Result<()>;
+
+    /// Forgets the download, keeping whatever has been downloaded.
+    async fn remove(&self, id: &str) -> Result<()>;
+
+    async fn files(&self, id: &str) -> Result<Vec<DownloadFile>>;
+
+    /// Downloads only the files of `indexes`, as in `DownloadFile::index`.
+    /// Clients unable to pick files keep downloading all of them.
+    async fn select(&self, _id: &str, _indexes: &[u32]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Forwards notifications to `tx` for as long as it is open. Clients
+    /// without notifications return right away and are only polled.
+    async fn subscribe(&self, _tx: mpsc::Sender<Notification>) {}
+
+    /// How often downloads are polled for their status.
+    fn poll_interval(&self) -> Duration;
+}
+
+static DOWNLOADER: OnceLock<Box<dyn Downloader>> = OnceLock::new();
+
+/// The download client chosen by `download.client`.
+pub fn downloader() -> &'static dyn Downloader {
+    DOWNLOADER
+        .get_or_init(|| match config().download.client {
+            DownloaderKind::Aria2 => {
+                let cfg = &config().aria2;
+                Box::new(Aria2::new(&cfg.url, cfg.secret.as_deref()))
+            }
+            DownloaderKind::Qbittorrent => Box::new(QBittorrent::new(&config().qbittorrent)),
+            DownloaderKind::Transmission => Box::new(Transmission::new(&config().transmission)),
+            DownloaderKind::Watch => Box::new(WatchFolder::new(&config().watch)),
+        })
+        .as_ref()
+}
+
+/// Lowercase hex infohash of a `magnet:?xt=urn:btih:` uri, which carries it
+/// either as 40 hex digits or as 32 base32 characters.
+pub fn magnet_info_hash(uri: &str) -> Option<String> {
+    let query = uri.strip_prefix("magnet:?")?;
+    let hash = query
+        .split('&')
+        .filter_map(|kv| kv.strip_prefix("xt="))
+        .find_map(|xt| {
+            xt.strip_prefix("urn:btih:")
+                .or_else(|| xt.strip_prefix("urn%3Abtih%3A"))
+        })?;
+    match hash.len() {
+        40 if hash.chars().all(|c| c.is_ascii_hexdigit()) => Some(hash.to_ascii_lowercase()),
+        32 => base32_to_hex(hash),
+        _ => None,
+    }
+}
+
+fn base32_to_hex(s: &str) -> Option<String> {
+    let mut bits: u64 = 0;
+    let mut n = 0;
+    let mut hex = String::with_capacity(40);
+    for c in s.chars() {
+        let v = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => return None,
+        };
+        bits = ((bits << 5) | v) & 0xffff;
+        n += 5;
+        while n >= 8 {
+            n -= 8;
+            hex += &format!("{:02x}", (bits >> n) & 0xff);
+        }
+    }
+    Some(hex)
+}
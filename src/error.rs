@@ -0,0 +1,49 @@
+use std::fmt::Display;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("db error: {0}")]
+    Db(#[from] rusqlite::Error),
+    #[error("aria2 error: {0}")]
+    Aria2(#[source] reqwest::Error),
+    #[error("aria2 rpc error {code}: {message}")]
+    Aria2Rpc { code: i64, message: String },
+    #[error("qbittorrent error: {0}")]
+    Qbittorrent(#[source] reqwest::Error),
+    #[error("qbittorrent api error: {0}")]
+    QbittorrentApi(String),
+    #[error("transmission error: {0}")]
+    Transmission(#[source] reqwest::Error),
+    #[error("transmission rpc error: {0}")]
+    TransmissionRpc(String),
+    #[error("torrent source error: {0}")]
+    Source(#[source] reqwest::Error),
+    #[error("invalid config: {0}")]
+    Config(String),
+    #[error("parse error: {0}")]
+    Parse(String),
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("illegal state: {0}")]
+    State(String),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn config(msg: impl Display) -> Self {
+        Error::Config(msg.to_string())
+    }
+
+    pub fn parse(msg: impl Display) -> Self {
+        Error::Parse(msg.to_string())
+    }
+}
+
+impl From<chrono::ParseError> for Error {
+    fn from(e: chrono::ParseError) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
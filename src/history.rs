@@ -0,0 +1,232 @@
+use crate::db::{collect_rows, db};
+use crate::downloader::magnet_info_hash;
+use crate::error::Result;
+use crate::source::Release;
+use chrono::{Local, NaiveDateTime};
+use rusqlite::{types::Type, Row};
+use std::collections::HashSet;
+
+/// What happened to the release of a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// the release was sent to the downloader
+    Download,
+    /// a better release took the place of the downloaded one
+    Replace,
+}
+
+impl Event {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Event::Download => "download",
+            Event::Replace => "replace",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "download" => Some(Event::Download),
+            "replace" => Some(Event::Replace),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct History {
+    pub task_id: u32,
+    pub bgm_id: u32,
+    pub episode: u32,
+    pub event: Event,
+    pub title: String,
+    pub uri: String,
+    /// title of the release this one replaced
+    pub replaced: String,
+    pub info_hash: String,
+    /// now if empty
+    pub time: String,
+}
+
+/// A release seen matching a subscription, keyed by its infohash and the bgm.
+#[derive(Debug)]
+pub struct Torrent {
+    pub info_hash: String,
+    pub title: String,
+    pub size: u64,
+    pub publish_time: String,
+    pub seen_time: String,
+    /// empty until sent to the downloader
+    pub sent_time: String,
+}
+
+fn now() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Key of `uri` in the torrent history: the infohash of a magnet, or the uri
+/// itself, e.g. for a .torrent link.
+pub fn uri_hash(uri: &str) -> String {
+    magnet_info_hash(uri).unwrap_or_else(|| uri.to_string())
+}
+
+pub fn add_history(h: &History) -> Result<()> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(
+        "INSERT INTO history(task_id, bgm_id, episode, event, title, uri, replaced, info_hash, time)
+            VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    )?;
+    let time = if h.time.is_empty() {
+        now()
+    } else {
+        h.time.clone()
+    };
+    stmt.execute(rusqlite::params![
+        h.task_id,
+        h.bgm_id,
+        h.episode,
+        h.event.as_str(),
+        h.title,
+        h.uri,
+        h.replaced,
+        h.info_hash,
+        time
+    ])?;
+    Ok(())
+}
+
+fn history_from_row(row: &Row) -> rusqlite::Result<History> {
+    let event: String = row.get(3)?;
+    Ok(History {
+        task_id: row.get(0)?,
+        bgm_id: row.get(1)?,
+        episode: row.get(2)?,
+        event: Event::parse(&event).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(3, Type::Text, event.into())
+        })?,
+        title: row.get(4)?,
+        uri: row.get(5)?,
+        replaced: row.get(6)?,
+        info_hash: row.get(7)?,
+        time: row.get(8)?,
+    })
+}
+
+/// Events of the tasks of a bgm, oldest first.
+pub fn get_history(bgm_id: u32) -> Result<Vec<History>> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(
+        "SELECT task_id, bgm_id, episode, event, title, uri, replaced, info_hash, time
+            FROM history WHERE bgm_id = ?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map([bgm_id], history_from_row)?;
+    Ok(collect_rows(rows, "history"))
+}
+
+/// Remembers releases seen matching a bgm, keeping what is known of the ones
+/// seen before.
+pub fn add_seen(bgm_id: u32, releases: &[&Release]) -> Result<()> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(
+        "INSERT OR IGNORE INTO torrent(info_hash, bgm_id, title, uri, size, publish_time, seen_time)
+            VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+    let now = now();
+    for r in releases {
+        stmt.execute(rusqlite::params![
+            r.hash(),
+            bgm_id,
+            r.title,
+            r.magnet,
+            r.size,
+            r.publish_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            now
+        ])?;
+    }
+    Ok(())
+}
+
+/// Records that `info_hash` was sent to the downloader for a bgm, seen or not.
+pub fn mark_sent(bgm_id: u32, info_hash: &str, title: &str, uri: &str) -> Result<()> {
+    let ctx = db();
+    let now = now();
+    ctx.execute(
+        "INSERT OR IGNORE INTO torrent(info_hash, bgm_id, title, uri, seen_time)
+            VALUES(?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![info_hash, bgm_id, title, uri, now],
+    )?;
+    ctx.execute(
+        "UPDATE torrent SET sent_time = ?3 WHERE info_hash = ?1 AND bgm_id = ?2",
+        rusqlite::params![info_hash, bgm_id, now],
+    )?;
+    Ok(())
+}
+
+/// Whether `info_hash` was sent to the downloader, for whichever bgm.
+pub fn is_sent(info_hash: &str) -> Result<bool> {
+    let ctx = db();
+    let mut stmt = ctx.prepare("SELECT 1 FROM torrent WHERE info_hash = ?1 AND sent_time <> ''")?;
+    Ok(stmt.exists([info_hash])?)
+}
+
+/// Infohashes that were sent to the downloader.
+pub fn sent_hashes() -> Result<HashSet<String>> {
+    let ctx = db();
+    let mut stmt = ctx.prepare("SELECT info_hash FROM torrent WHERE sent_time <> ''")?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    Ok(collect_rows(rows, "torrent").into_iter().collect())
+}
+
+/// Lets the releases once sent for a task be sent again, returns how many.
+pub fn unsend_task(task_id: u32) -> Result<usize> {
+    let ctx = db();
+    Ok(ctx.execute(
+        "UPDATE torrent SET sent_time = '' WHERE sent_time <> '' AND EXISTS (
+            SELECT 1 FROM history h WHERE h.task_id = ?1 AND h.info_hash <> ''
+                AND h.info_hash = torrent.info_hash AND h.bgm_id = torrent.bgm_id)",
+        [task_id],
+    )?)
+}
+
+/// Releases seen for a bgm, oldest first.
+pub fn get_torrents(bgm_id: u32) -> Result<Vec<Torrent>> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(
+        "SELECT info_hash, title, size, publish_time, seen_time, sent_time
+            FROM torrent WHERE bgm_id = ?1 ORDER BY seen_time, publish_time",
+    )?;
+    let rows = stmt.query_map([bgm_id], |row| {
+        Ok(Torrent {
+            info_hash: row.get(0)?,
+            title: row.get(1)?,
+            size: row.get(2)?,
+            publish_time: row.get(3)?,
+            seen_time: row.get(4)?,
+            sent_time: row.get(5)?,
+        })
+    })?;
+    Ok(collect_rows(rows, "torrent"))
+}
+
+/// Releases seen matching a bgm that were never sent, as far as the history
+/// knows them.
+pub fn get_seen(bgm_id: u32) -> Result<Vec<Release>> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(
+        "SELECT info_hash, title, uri, size, publish_time FROM torrent
+            WHERE bgm_id = ?1 AND sent_time = '' ORDER BY publish_time",
+    )?;
+    let rows = stmt.query_map([bgm_id], |row| {
+        let publish_time: String = row.get(4)?;
+        Ok(Release {
+            info_hash: row.get(0)?,
+            title: row.get(1)?,
+            magnet: row.get(2)?,
+            size: row.get(3)?,
+            publish_time: NaiveDateTime::parse_from_str(&publish_time, "%Y-%m-%d %H:%M:%S")
+                .unwrap_or_default(),
+            seeders: None,
+            files: Vec::new(),
+        })
+    })?;
+    Ok(collect_rows(rows, "torrent"))
+}
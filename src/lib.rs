@@ -1,10 +1,25 @@
+pub mod aria2;
 mod bgminfo;
+pub mod cli;
+pub mod config;
 mod db;
-// mod history;
-pub mod aria2;
+mod dmhy;
+pub mod downloader;
+pub mod error;
+mod history;
 mod log;
+mod mikan;
 mod moe;
+mod nyaa;
 mod proc;
+pub mod qbittorrent;
+mod rss;
+mod score;
+mod source;
+mod state;
 pub mod task;
 mod taskinfo;
+mod title;
+pub mod transmission;
+pub mod watch;
 // pub mod weibo;
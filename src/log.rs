@@ -1,3 +1,4 @@
+use crate::config::config;
 use tracing_appender::{
     non_blocking::{NonBlocking, WorkerGuard},
     rolling::never,
@@ -9,7 +10,7 @@ pub fn init_log() -> WorkerGuard {
     );
     let time_offset = time::UtcOffset::from_hms(8, 0, 0).unwrap();
     let timer = tracing_subscriber::fmt::time::OffsetTime::new(time_offset, time_fmt);
-    let (non_blocking, guard) = NonBlocking::new(never("I:/programs/bangumi", "bgm.log"));
+    let (non_blocking, guard) = NonBlocking::new(never(&config().log.dir, &config().log.file));
 
     tracing_subscriber::fmt::fmt()
         .with_max_level(tracing::Level::INFO)
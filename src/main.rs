@@ -1,6 +1,14 @@
-use bgm::task::exec;
+use bgm::cli::{run, Cli};
+use bgm::error::Error;
+use clap::Parser;
 
 #[tokio::main]
 async fn main() {
-    exec().await.unwrap();
+    if let Err(e) = run(Cli::parse()).await {
+        eprintln!("{e}");
+        std::process::exit(match e {
+            Error::Config(_) => 2,
+            _ => 1,
+        });
+    }
 }
@@ -0,0 +1,35 @@
+CREATE TABLE IF NOT EXISTS bgm (
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    name          TEXT    NOT NULL,
+    chinese       TEXT,
+    start_date    TEXT,
+    weekday       INTEGER NOT NULL,
+    clock         INTEGER,
+    episode       INTEGER,
+    episode_count INTEGER,
+    regex         TEXT    NOT NULL,
+    path          TEXT,
+    state         INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS task (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    bgm_id      INTEGER NOT NULL REFERENCES bgm(id),
+    episode     INTEGER NOT NULL,
+    regex       TEXT    NOT NULL,
+    path        TEXT    NOT NULL,
+    uri         TEXT    NOT NULL DEFAULT '',
+    gid         TEXT    NOT NULL DEFAULT '',
+    exec_time   TEXT    NOT NULL,
+    create_time TEXT    NOT NULL DEFAULT '',
+    finish_time TEXT    NOT NULL DEFAULT '',
+    state       INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS task_state ON task(state, exec_time);
+
+CREATE TABLE IF NOT EXISTS procs (
+    name TEXT PRIMARY KEY,
+    cmd  TEXT NOT NULL,
+    args TEXT NOT NULL DEFAULT ''
+);
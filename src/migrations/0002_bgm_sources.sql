@@ -0,0 +1,2 @@
+-- torrent sources of a bgm as a JSON array of specs, empty for the configured default
+ALTER TABLE bgm ADD COLUMN sources TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,4 @@
+-- hours a task waits for a release of a preferred group, 0 takes the best at once
+ALTER TABLE bgm ADD COLUMN grace_hours INTEGER NOT NULL DEFAULT 0;
+-- when the waiting task stops waiting, set when its first candidate shows up
+ALTER TABLE task ADD COLUMN grace_deadline TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,24 @@
+-- hours after airing a done task keeps looking for a better release, 0 never
+ALTER TABLE bgm ADD COLUMN upgrade_hours INTEGER NOT NULL DEFAULT 0;
+-- title of the release sent for the task
+ALTER TABLE task ADD COLUMN title TEXT NOT NULL DEFAULT '';
+-- JSON array of the files the finished download wrote
+ALTER TABLE task ADD COLUMN files TEXT NOT NULL DEFAULT '';
+-- JSON array of the files of the release being replaced, deleted once the
+-- replacement finishes
+ALTER TABLE task ADD COLUMN replaces TEXT NOT NULL DEFAULT '';
+
+CREATE TABLE IF NOT EXISTS history (
+    id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    task_id   INTEGER NOT NULL,
+    bgm_id    INTEGER NOT NULL,
+    episode   INTEGER NOT NULL,
+    event     TEXT    NOT NULL,
+    title     TEXT    NOT NULL DEFAULT '',
+    uri       TEXT    NOT NULL DEFAULT '',
+    -- title of the release this one replaced
+    replaced  TEXT    NOT NULL DEFAULT '',
+    time      TEXT    NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS history_bgm ON history(bgm_id, episode);
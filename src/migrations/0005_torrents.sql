@@ -0,0 +1,18 @@
+-- every release seen matching a subscription, and whether it was sent to the
+-- downloader
+CREATE TABLE IF NOT EXISTS torrent (
+    -- lowercase hex infohash, the uri itself for links that don't tell it
+    info_hash    TEXT    PRIMARY KEY,
+    bgm_id       INTEGER NOT NULL,
+    title        TEXT    NOT NULL DEFAULT '',
+    uri          TEXT    NOT NULL DEFAULT '',
+    size         INTEGER NOT NULL DEFAULT 0,
+    publish_time TEXT    NOT NULL DEFAULT '',
+    seen_time    TEXT    NOT NULL,
+    -- '' until sent, and again once a user forces it to be sent anew
+    sent_time    TEXT    NOT NULL DEFAULT ''
+);
+
+CREATE INDEX IF NOT EXISTS torrent_bgm ON torrent(bgm_id);
+
+ALTER TABLE history ADD COLUMN info_hash TEXT NOT NULL DEFAULT '';
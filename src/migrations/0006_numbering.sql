@@ -0,0 +1,6 @@
+-- absolute numbers of a split-cour show are the season's plus this offset
+ALTER TABLE bgm ADD COLUMN episode_offset INTEGER NOT NULL DEFAULT 0;
+-- numbering of the tasks, 'season' or 'absolute'
+ALTER TABLE bgm ADD COLUMN numbering TEXT NOT NULL DEFAULT 'season';
+-- JSON array of the numberings releases may use, '' for both
+ALTER TABLE bgm ADD COLUMN accept TEXT NOT NULL DEFAULT '';
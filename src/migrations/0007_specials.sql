@@ -0,0 +1,2 @@
+-- sp2, ova1, recap or 12.5 for a special episode, '' for a numbered one
+ALTER TABLE task ADD COLUMN special TEXT NOT NULL DEFAULT '';
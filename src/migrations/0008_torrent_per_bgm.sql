@@ -0,0 +1,24 @@
+-- a release may match several subscriptions, key the torrents by both so
+-- each of them keeps its own
+CREATE TABLE torrent_per_bgm (
+    -- lowercase hex infohash, the uri itself for links that don't tell it
+    info_hash    TEXT    NOT NULL,
+    bgm_id       INTEGER NOT NULL,
+    title        TEXT    NOT NULL DEFAULT '',
+    uri          TEXT    NOT NULL DEFAULT '',
+    size         INTEGER NOT NULL DEFAULT 0,
+    publish_time TEXT    NOT NULL DEFAULT '',
+    seen_time    TEXT    NOT NULL,
+    -- '' until sent, and again once a user forces it to be sent anew
+    sent_time    TEXT    NOT NULL DEFAULT '',
+    PRIMARY KEY (info_hash, bgm_id)
+);
+
+INSERT INTO torrent_per_bgm SELECT
+    info_hash, bgm_id, title, uri, size, publish_time, seen_time, sent_time
+    FROM torrent;
+
+DROP TABLE torrent;
+ALTER TABLE torrent_per_bgm RENAME TO torrent;
+
+CREATE INDEX IF NOT EXISTS torrent_bgm ON torrent(bgm_id);
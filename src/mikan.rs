@@ -0,0 +1,157 @@
+use crate::config::config;
+use crate::error::{Error, Result};
+use crate::source::{self, child_text, Release, TorrentSource};
+use async_trait::async_trait;
+use chrono::{FixedOffset, Local, NaiveDateTime, TimeZone};
+use tracing::error;
+
+#[cfg(test)]
+mod tests;
+
+const TRACKERS: &[&str] = &[
+    "http://open.acgtracker.com:1096/announce",
+    "udp://tracker.opentrackr.org:1337/announce",
+];
+
+/// Which feed of Mikan Project to read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Feed {
+    /// the subscriptions of the account owning `mikan.token`
+    MyBangumi,
+    /// a bangumi, optionally narrowed to one subgroup
+    Bangumi { id: u32, subgroup: Option<u32> },
+    /// any other feed url of the site
+    Url(String),
+}
+
+impl Feed {
+    /// `<bangumi id>`, `<bangumi id>/<subgroup id>` or a feed url.
+    pub fn parse(arg: &str) -> Result<Self> {
+        if arg.starts_with("http://") || arg.starts_with("https://") {
+            return Ok(Feed::Url(arg.to_string()));
+        }
+        let invalid = || {
+            Error::parse(format!(
+                "mikan feed `{arg}` is not <bangumi id>[/<subgroup id>]"
+            ))
+        };
+        let (id, subgroup) = match arg.split_once('/') {
+            Some((id, subgroup)) => (id, Some(subgroup.parse().map_err(|_| invalid())?)),
+            None => (arg, None),
+        };
+        Ok(Feed::Bangumi {
+            id: id.parse().map_err(|_| invalid())?,
+            subgroup,
+        })
+    }
+
+    fn url(&self) -> Result<String> {
+        let base = config().mikan.url.trim_end_matches('/');
+        Ok(match self {
+            Feed::MyBangumi => {
+                let token = config()
+                    .mikan
+                    .token
+                    .as_ref()
+                    .ok_or_else(|| Error::config("the mikan source needs mikan.token"))?;
+                format!("{base}/RSS/MyBangumi?token={token}")
+            }
+            Feed::Bangumi {
+                id,
+                subgroup: Some(subgroup),
+            } => format!("{base}/RSS/Bangumi?bangumiId={id}&subgroupid={subgroup}"),
+            Feed::Bangumi { id, subgroup: None } => format!("{base}/RSS/Bangumi?bangumiId={id}"),
+            Feed::Url(url) => url.clone(),
+        })
+    }
+}
+
+/// RSS feeds of mikanani.me.
+pub struct Mikan {
+    feed: Feed,
+}
+
+impl Mikan {
+    pub fn new(feed: Feed) -> Self {
+        Mikan { feed }
+    }
+
+    /// The url of the token feed holds the token, keep it out of the logs.
+    fn source_error(&self, e: reqwest::Error) -> Error {
+        match self.feed {
+            Feed::MyBangumi => Error::Source(e.without_url()),
+            _ => Error::Source(e),
+        }
+    }
+}
+
+fn parse_item(item: roxmltree::Node) -> Result<Release> {
+    let title =
+        child_text(item, "title").ok_or_else(|| Error::parse("mikan item without title"))?;
+    let enclosure = item
+        .children()
+        .find(|c| c.has_tag_name("enclosure"))
+        .and_then(|c| c.attribute("url"));
+    let torrent = item.children().find(|c| c.tag_name().name() == "torrent");
+    let info_hash = enclosure
+        .and_then(source::hash_in_link)
+        .or_else(|| child_text(item, "link").and_then(source::hash_in_link))
+        .ok_or_else(|| Error::parse(format!("no info hash in mikan item {title}")))?;
+
+    // published in China time without an offset
+    let pub_date = torrent
+        .and_then(|t| child_text(t, "pubDate"))
+        .ok_or_else(|| Error::parse(format!("mikan item {title} without pubDate")))?;
+    let pub_date = NaiveDateTime::parse_from_str(pub_date, "%Y-%m-%dT%H:%M:%S%.f")?;
+    let china = FixedOffset::east_opt(8 * 3600).expect("valid offset");
+    let publish_time = china
+        .from_local_datetime(&pub_date)
+        .single()
+        .ok_or_else(|| Error::parse(format!("invalid pubDate of mikan item {title}")))?
+        .with_timezone(&Local)
+        .naive_local();
+
+    Ok(Release {
+        magnet: source::magnet(&info_hash, title, TRACKERS),
+        info_hash,
+        size: torrent
+            .and_then(|t| child_text(t, "contentLength"))
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default(),
+        publish_time,
+        seeders: None,
+        files: Vec::new(),
+        title: title.to_string(),
+    })
+}
+
+/// Releases of a Mikan RSS document.
+pub fn parse_feed(xml: &str) -> Result<Vec<Release>> {
+    let doc = roxmltree::Document::parse(xml).map_err(Error::parse)?;
+    Ok(doc
+        .descendants()
+        .filter(|n| n.has_tag_name("item"))
+        .filter_map(|item| {
+            parse_item(item)
+                .map_err(|e| error!("skip mikan item: {}", e))
+                .ok()
+        })
+        .collect())
+}
+
+#[async_trait]
+impl TorrentSource for Mikan {
+    /// Feeds list every episode of a bangumi, `since` needs no paging.
+    async fn releases(&self, _since: &NaiveDateTime) -> Result<Vec<Release>> {
+        let xml = source::client()?
+            .get(self.feed.url()?)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| self.source_error(e))?
+            .text()
+            .await
+            .map_err(|e| self.source_error(e))?;
+        parse_feed(&xml)
+    }
+}
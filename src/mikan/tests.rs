@@ -0,0 +1,67 @@
+use super::*;
+
+/// Mikan tells the time in China.
+fn china(s: &str) -> NaiveDateTime {
+    FixedOffset::east_opt(8 * 3600)
+        .unwrap()
+        .from_local_datetime(&NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f").unwrap())
+        .unwrap()
+        .with_timezone(&Local)
+        .naive_local()
+}
+
+#[test]
+fn feed() {
+    let releases = parse_feed(include_str!("feed.xml")).unwrap();
+    // no infohash or no pubDate, skipped
+    assert_eq!(releases.len(), 2);
+
+    let r = &releases[0];
+    assert_eq!(
+        r.title,
+        "[喵萌奶茶屋&LoliHouse] 葬送的芙莉莲 / Sousou no Frieren - 05 [WebRip 1080p HEVC-10bit AAC][简繁日内封字幕]"
+    );
+    // from the .torrent of the enclosure
+    assert_eq!(r.info_hash, "0a1b2c3d4e5f60718293a4b5c6d7e8f901234567");
+    assert!(r
+        .magnet
+        .starts_with("magnet:?xt=urn:btih:0a1b2c3d4e5f60718293a4b5c6d7e8f901234567&dn="));
+    assert_eq!(r.size, 647495680);
+    assert_eq!(r.publish_time, china("2023-10-13 23:10:12.354"));
+
+    // without an enclosure, from the episode link
+    let r = &releases[1];
+    assert_eq!(r.info_hash, "ffeeddccbbaa99887766554433221100ffeeddcc");
+    assert_eq!(r.size, 0);
+    assert_eq!(r.publish_time, china("2023-10-20 23:00:00"));
+}
+
+#[test]
+fn feeds() {
+    assert_eq!(
+        Feed::parse("3141").unwrap(),
+        Feed::Bangumi {
+            id: 3141,
+            subgroup: None
+        }
+    );
+    assert_eq!(
+        Feed::parse("3141/382").unwrap(),
+        Feed::Bangumi {
+            id: 3141,
+            subgroup: Some(382)
+        }
+    );
+    let url = "https://mikanani.me/RSS/Search?searchstr=frieren";
+    assert_eq!(Feed::parse(url).unwrap(), Feed::Url(url.to_string()));
+    for arg in [
+        "",
+        "frieren",
+        "3141/",
+        "3141/lolihouse",
+        "/382",
+        "mikanani.me/RSS",
+    ] {
+        assert!(Feed::parse(arg).is_err(), "{arg}");
+    }
+}
@@ -1,12 +1,22 @@
 #![allow(non_snake_case)]
-const LATEST_URL: &'static str = "https://bangumi.moe/api/torrent/latest";
-const TORRENT_URL: &'static str = "https://bangumi.moe/api/torrent/page";
-const HTTP_PROXY: &'static str = "http://127.0.0.1:7890";
-const UA: &'static str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/109.0.0.0 Safari/537.36";
+const LATEST_URL: &str = "https://bangumi.moe/api/torrent/latest";
+const TORRENT_URL: &str = "https://bangumi.moe/api/torrent/page";
+const SEARCH_URL: &str = "https://bangumi.moe/api/v2/torrent/search";
+const TAG_SEARCH_URL: &str = "https://bangumi.moe/api/torrent/search";
+/// Pages a search may walk back, the crawl of the latest ones goes to 99.
+const SEARCH_PAGES: u32 = 20;
 
+use crate::error::{Error, Result};
+use crate::source::{self, Release, TorrentSource};
+use async_trait::async_trait;
 use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
-use reqwest::{header::*, Client};
-use serde::{Deserialize, Serialize};
+use reqwest::Client;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+use tracing::{error, warn};
+
+#[cfg(test)]
+mod tests;
 
 use std::collections::HashMap;
 
@@ -38,48 +48,118 @@ pub struct Torrent {
     sync: Option<HashMap<String, String>>,
 }
 
+impl TryFrom<Torrent> for Release {
+    type Error = Error;
+
+    fn try_from(t: Torrent) -> Result<Self> {
+        let publish_time = DateTime::parse_from_rfc3339(&t.publish_time)?.with_timezone(&Local);
+        Ok(Release {
+            size: source::parse_size(&t.size).unwrap_or_default(),
+            info_hash: t.infoHash.to_ascii_lowercase(),
+            publish_time: publish_time.naive_local(),
+            seeders: Some(t.seeders as u32),
+            files: t
+                .content
+                .into_iter()
+                .filter_map(|c| c.into_iter().next())
+                .collect(),
+            title: t.title,
+            magnet: t.magnet,
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 struct LatestRsp {
+    #[serde(default)]
     page_count: u32,
+    #[serde(deserialize_with = "skip_malformed")]
     torrents: Vec<Torrent>,
 }
 
-pub async fn get_torrents(
-    earliest: &NaiveDateTime,
-) -> Result<Vec<Torrent>, Box<dyn std::error::Error>> {
-    let c = Client::builder()
-        .proxy(reqwest::Proxy::all(HTTP_PROXY)?)
-        .build()?;
-    let mut torrents: Vec<Torrent> = Vec::new();
-    let mut n = 1;
-    while n < 100 {
-        let url = if n == 1 {
-            LATEST_URL.to_string()
-        } else {
-            format!("{}/{}", TORRENT_URL, n)
+/// The torrents of a page, a malformed one can't spoil the others.
+fn skip_malformed<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<Torrent>, D::Error> {
+    Ok(Vec::<Value>::deserialize(d)?
+        .into_iter()
+        .filter_map(|v| {
+            serde_json::from_value(v)
+                .map_err(|e| error!("skip malformed moe torrent: {}", e))
+                .ok()
+        })
+        .collect())
+}
+
+/// Releases of `torrents`, skipping the ones that don't make one.
+fn releases_of(torrents: Vec<Torrent>) -> Vec<Release> {
+    torrents
+        .into_iter()
+        .filter_map(|t| {
+            let title = t.title.clone();
+            Release::try_from(t)
+                .map_err(|e| error!("skip moe torrent {}: {}", title, e))
+                .ok()
+        })
+        .collect()
+}
+
+/// What to ask bangumi.moe for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// everything, newest first
+    Latest,
+    /// torrents whose title has these words
+    Keywords(String),
+    /// torrents with all of these tag ids
+    Tags(Vec<String>),
+}
+
+impl Query {
+    async fn page(&self, c: &Client, n: u32) -> Result<LatestRsp> {
+        let req = match self {
+            Query::Latest if n == 1 => c.get(LATEST_URL),
+            Query::Latest => c.get(format!("{}/{}", TORRENT_URL, n)),
+            Query::Keywords(query) => c.post(SEARCH_URL).json(&json!({ "query": query, "p": n })),
+            Query::Tags(ids) => c
+                .post(TAG_SEARCH_URL)
+                .json(&json!({ "tag_id": ids, "p": n })),
         };
-        let mut rsp: LatestRsp = c
-            .get(url.clone())
-            .header(USER_AGENT, UA)
-            .send()
-            .await?
+        req.send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(Error::Source)?
             .json()
-            .await?;
+            .await
+            .map_err(Error::Source)
+    }
+}
 
-        let earliest_released = rsp
-            .torrents
-            .iter_mut()
-            .min_by(|t1, t2| t1.publish_time.cmp(&t2.publish_time));
+/// Torrents of `query`, page by page until `earliest` is reached.
+pub async fn get_torrents(query: &Query, earliest: &NaiveDateTime) -> Result<Vec<Torrent>> {
+    let c = source::client()?;
+    let max_pages = if *query == Query::Latest {
+        99
+    } else {
+        SEARCH_PAGES
+    };
+    let mut torrents: Vec<Torrent> = Vec::new();
+    let mut n = 1;
+    while n <= max_pages {
+        let rsp = query.page(&c, n).await?;
+        let page_count = rsp.page_count;
 
-        let earliest_publish = Local::from_utc_datetime(
-            &Local,
-            &DateTime::parse_from_rfc3339(&earliest_released.unwrap().publish_time)
-                .unwrap()
-                .naive_utc(),
-        );
+        // torrents of a malformed publish_time are skipped later on
+        let Some(earliest_publish) = rsp
+            .torrents
+            .iter()
+            .filter_map(|t| DateTime::parse_from_rfc3339(&t.publish_time).ok())
+            .map(|t| Local::from_utc_datetime(&Local, &t.naive_utc()))
+            .min()
+        else {
+            break;
+        };
 
         torrents.extend(rsp.torrents);
-        if earliest_publish.naive_local() <= *earliest {
+        if earliest_publish.naive_local() <= *earliest || (page_count > 0 && n >= page_count) {
             break;
         }
         n += 1;
@@ -87,3 +167,54 @@ pub async fn get_torrents(
     }
     Ok(torrents)
 }
+
+/// Torrents of bangumi.moe, searched for or crawled from the latest ones.
+pub struct Moe {
+    query: Query,
+}
+
+impl Moe {
+    pub fn latest() -> Self {
+        Moe {
+            query: Query::Latest,
+        }
+    }
+
+    /// `arg` is either title keywords or `tag=<id>[,<id>..]`.
+    pub fn parse(arg: &str) -> Result<Self> {
+        let query = match arg.strip_prefix("tag=") {
+            Some(ids) => {
+                let ids: Vec<String> = ids.split(',').map(|id| id.trim().to_string()).collect();
+                if ids
+                    .iter()
+                    .any(|id| id.len() != 24 || !id.chars().all(|c| c.is_ascii_hexdigit()))
+                {
+                    return Err(Error::parse(format!(
+                        "moe tag ids `{arg}` are not 24 hex digits each"
+                    )));
+                }
+                Query::Tags(ids)
+            }
+            None if arg.trim().is_empty() => Query::Latest,
+            None => Query::Keywords(arg.trim().to_string()),
+        };
+        Ok(Moe { query })
+    }
+}
+
+#[async_trait]
+impl TorrentSource for Moe {
+    async fn releases(&self, since: &NaiveDateTime) -> Result<Vec<Release>> {
+        let torrents = match get_torrents(&self.query, since).await {
+            Err(e) if self.query != Query::Latest => {
+                warn!(
+                    "search bangumi.moe for {:?} error:{}, crawl the latest torrents",
+                    self.query, e
+                );
+                get_torrents(&Query::Latest, since).await?
+            }
+            r => r?,
+        };
+        Ok(releases_of(torrents))
+    }
+}
@@ -0,0 +1,60 @@
+use super::*;
+
+fn local(rfc3339: &str) -> NaiveDateTime {
+    DateTime::parse_from_rfc3339(rfc3339)
+        .unwrap()
+        .with_timezone(&Local)
+        .naive_local()
+}
+
+#[test]
+fn page() {
+    let rsp: LatestRsp = serde_json::from_str(include_str!("latest.json")).unwrap();
+    assert_eq!(rsp.page_count, 3);
+    // the torrent missing fields is left out of the page
+    assert_eq!(rsp.torrents.len(), 3);
+
+    // and the one of an invalid publish_time out of the releases
+    let releases = releases_of(rsp.torrents);
+    assert_eq!(releases.len(), 2);
+
+    let r = &releases[0];
+    assert!(r.title.starts_with("[LoliHouse] 葬送的芙莉莲"));
+    assert_eq!(r.info_hash, "c0ffee00112233445566778899aabbccddeeff00");
+    assert!(r
+        .magnet
+        .starts_with("magnet:?xt=urn:btih:C0FFEE00112233445566778899AABBCCDDEEFF00"));
+    assert_eq!(r.hash(), r.info_hash);
+    assert_eq!(r.size, 751304704);
+    assert_eq!(r.seeders, Some(87));
+    assert_eq!(r.publish_time, local("2023-10-13T14:40:01.123Z"));
+    assert_eq!(
+        r.file_name(),
+        Some("[LoliHouse] Sousou no Frieren - 05 [WebRip 1080p HEVC-10bit AAC ASSx2].mkv")
+    );
+
+    let r = &releases[1];
+    assert_eq!(r.info_hash, "2222222222222222222222222222222222222222");
+    assert_eq!(r.size, 315831091);
+    assert_eq!(r.publish_time, local("2023-10-20T15:02:00.000Z"));
+}
+
+#[test]
+fn specs() {
+    assert_eq!(Moe::parse("").unwrap().query, Query::Latest);
+    assert_eq!(
+        Moe::parse(" frieren 1080p ").unwrap().query,
+        Query::Keywords("frieren 1080p".to_string())
+    );
+    assert_eq!(
+        Moe::parse("tag=548ee0ea4ab7379536f56354,6527ab1d42d4a3e9c6a0b1c2")
+            .unwrap()
+            .query,
+        Query::Tags(vec![
+            "548ee0ea4ab7379536f56354".to_string(),
+            "6527ab1d42d4a3e9c6a0b1c2".to_string()
+        ])
+    );
+    assert!(Moe::parse("tag=frieren").is_err());
+    assert!(Moe::parse("tag=548ee0ea4ab7379536f56354,").is_err());
+}
@@ -0,0 +1,85 @@
+use crate::config::config;
+use crate::error::{Error, Result};
+use crate::source::{self, child_text, Release, TorrentSource};
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use tracing::error;
+
+#[cfg(test)]
+mod tests;
+
+const TRACKERS: &[&str] = &[
+    "http://nyaa.tracker.wf:7777/announce",
+    "udp://open.stealth.si:80/announce",
+    "udp://tracker.opentrackr.org:1337/announce",
+];
+
+/// The RSS feed of nyaa.si, searched for `query` unless it is empty.
+pub struct Nyaa {
+    query: String,
+}
+
+impl Nyaa {
+    pub fn new(query: &str) -> Self {
+        Nyaa {
+            query: query.to_string(),
+        }
+    }
+}
+
+fn parse_item(item: roxmltree::Node) -> Result<Release> {
+    let field = |name| {
+        child_text(item, name).ok_or_else(|| Error::parse(format!("nyaa item without {name}")))
+    };
+    let title = field("title")?;
+    let info_hash = field("infoHash")?.to_ascii_lowercase();
+    Ok(Release {
+        magnet: source::magnet(&info_hash, title, TRACKERS),
+        info_hash,
+        size: child_text(item, "size")
+            .and_then(source::parse_size)
+            .unwrap_or_default(),
+        publish_time: source::parse_pub_date(field("pubDate")?)?,
+        seeders: child_text(item, "seeders").and_then(|s| s.parse().ok()),
+        files: Vec::new(),
+        title: title.to_string(),
+    })
+}
+
+/// Releases of a nyaa flavoured RSS document.
+pub fn parse_feed(xml: &str) -> Result<Vec<Release>> {
+    let doc = roxmltree::Document::parse(xml).map_err(Error::parse)?;
+    Ok(doc
+        .descendants()
+        .filter(|n| n.has_tag_name("item"))
+        .filter_map(|item| {
+            parse_item(item)
+                .map_err(|e| error!("skip nyaa item: {}", e))
+                .ok()
+        })
+        .collect())
+}
+
+#[async_trait]
+impl TorrentSource for Nyaa {
+    /// The feed only carries the latest 75 items, older ones are out of reach.
+    async fn releases(&self, _since: &NaiveDateTime) -> Result<Vec<Release>> {
+        let cfg = &config().nyaa;
+        let xml = source::client()?
+            .get(&cfg.url)
+            .query(&[
+                ("page", "rss"),
+                ("q", &self.query),
+                ("c", &cfg.category),
+                ("f", "0"),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(Error::Source)?
+            .text()
+            .await
+            .map_err(Error::Source)?;
+        parse_feed(&xml)
+    }
+}
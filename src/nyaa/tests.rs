@@ -0,0 +1,43 @@
+use super::*;
+use chrono::{DateTime, Local};
+
+fn local(rfc2822: &str) -> NaiveDateTime {
+    DateTime::parse_from_rfc2822(rfc2822)
+        .unwrap()
+        .with_timezone(&Local)
+        .naive_local()
+}
+
+#[test]
+fn feed() {
+    let releases = parse_feed(include_str!("feed.xml")).unwrap();
+    // the items without an infohash or a valid pubDate are skipped
+    assert_eq!(releases.len(), 2);
+
+    let r = &releases[0];
+    assert_eq!(
+        r.title,
+        "[SubsPlease] Sousou no Frieren - 03 (1080p) [A1B2C3D4].mkv"
+    );
+    assert_eq!(r.info_hash, "0a1b2c3d4e5f60718293a4b5c6d7e8f901234567");
+    assert!(r
+        .magnet
+        .starts_with("magnet:?xt=urn:btih:0a1b2c3d4e5f60718293a4b5c6d7e8f901234567"));
+    assert_eq!(r.size, 1503238553);
+    assert_eq!(r.seeders, Some(1520));
+    assert_eq!(r.publish_time, local("Fri, 13 Oct 2023 14:32:05 -0000"));
+
+    let r = &releases[1];
+    assert_eq!(r.info_hash, "ffeeddccbbaa99887766554433221100ffeeddcc");
+    assert_eq!(r.size, 379374796);
+    assert_eq!(r.seeders, None);
+    assert_eq!(r.publish_time, local("Sat, 14 Oct 2023 01:00:00 -0000"));
+}
+
+#[test]
+fn not_a_feed() {
+    assert!(parse_feed("<rss><channel>").is_err());
+    assert!(parse_feed("<rss><channel></channel></rss>")
+        .unwrap()
+        .is_empty());
+}
@@ -1,68 +1,204 @@
-#![allow(dead_code)]
-use crate::db::db;
-use rusqlite::{params, Rows};
-use std::os::windows::process::CommandExt;
+use crate::config::{config, HealthCheck};
+use crate::db::{collect_rows, db};
+use crate::error::Result;
+use std::path::Path;
 use std::process::{Command, Stdio};
-fn kill(name: &str) {
-    exec("taskkill", ["/f", "/t", "/im", name]);
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::TcpStream,
+    sync::watch,
+    time::{sleep, timeout, timeout_at, Duration, Instant},
+};
+use tracing::{error, info, warn};
+
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+use unix as sys;
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+use windows as sys;
+
+/// A process that stays up this long gets its backoff reset.
+const STABLE_SECS: u64 = 60;
+
+fn get_filename_from_path(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|f| f.to_str())
+        .unwrap_or(path)
 }
 
-pub fn kill_aria2c() {
-    kill("aria2c.exe");
+pub fn is_proc_running(name: &str) -> bool {
+    sys::is_running(name)
 }
 
-fn get_filename_from_path(path: &str) -> &str {
-    match path.rsplit_once('/') {
-        Some((_, filename)) => filename,
-        None => path,
-    }
+struct Entry {
+    name: String,
+    cmd: String,
+    args: Vec<String>,
 }
 
-fn is_proc_running(name: &str) -> bool {
-    let output = Command::new("tasklist")
-        .output()
-        .expect("Failed to execute command");
+fn get_entries() -> Result<Vec<Entry>> {
+    let ctx = db();
+    let mut stmt = ctx.prepare("select name, cmd, args from procs")?;
+    let entries = stmt.query_map([], |row| {
+        let args: String = row.get(2).unwrap_or_default();
+        Ok(Entry {
+            name: row.get(0)?,
+            cmd: row.get(1)?,
+            args: args.split_whitespace().map(str::to_string).collect(),
+        })
+    })?;
+    Ok(collect_rows(entries, "procs"))
+}
 
-    let output_str = String::from_utf8_lossy(&output.stdout);
-    output_str.lines().any(|line| line.contains(name))
+async fn is_healthy(addr: &str) -> bool {
+    matches!(
+        timeout(Duration::from_secs(3), TcpStream::connect(addr)).await,
+        Ok(Ok(_))
+    )
 }
 
-pub fn exec<I, S>(name: &str, args: I)
+fn forward<R>(name: String, stream: R, is_err: bool)
 where
-    I: IntoIterator<Item = S>,
-    S: AsRef<std::ffi::OsStr>,
+    R: tokio::io::AsyncRead + Unpin + Send + 'static,
 {
-    Command::new(name)
-        .args(args)
-        .creation_flags(0x00000008)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()
-        .unwrap();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stream).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if is_err {
+                warn!(target: "proc", "[{name}] {line}");
+            } else {
+                info!(target: "proc", "[{name}] {line}");
+            }
+        }
+    });
+}
+
+fn spawn(entry: &Entry) -> std::io::Result<tokio::process::Child> {
+    let mut cmd = Command::new(&entry.cmd);
+    cmd.args(&entry.args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    sys::detach(&mut cmd);
+    let mut child = tokio::process::Command::from(cmd)
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(out) = child.stdout.take() {
+        forward(entry.name.clone(), out, false);
+    }
+    if let Some(err) = child.stderr.take() {
+        forward(entry.name.clone(), err, true);
+    }
+    Ok(child)
 }
 
-fn run_by_row(rows: &mut Rows) {
-    while let Some(row) = rows.next().unwrap() {
-        let cmd: String = row.get(0).unwrap();
-        let args: String = row.get(1).unwrap();
-        let args: Vec<_> = args.split_whitespace().collect();
-        if !is_proc_running(get_filename_from_path(cmd.as_str())) {
-            exec(cmd.as_str(), args);
+/// Resolves once `check` has failed `retries` times in a row, ignoring
+/// failures until the process has been healthy once or the startup window
+/// has passed.
+async fn unhealthy(name: &str, check: &HealthCheck, healthy: &watch::Sender<bool>) {
+    let started = Instant::now();
+    let grace = Duration::from_secs(config().supervisor.startup_timeout);
+    let mut failures = 0;
+    loop {
+        sleep(Duration::from_secs(check.interval)).await;
+        if is_healthy(&check.addr).await {
+            failures = 0;
+            healthy.send_replace(true);
+        } else if *healthy.borrow() || started.elapsed() > grace {
+            failures += 1;
+            warn!(
+                "proc {} health check {} failed ({failures}/{})",
+                name, check.addr, check.retries
+            );
+            if failures >= check.retries {
+                healthy.send_replace(false);
+                return;
+            }
         }
     }
 }
-pub fn run_proc(name: &str) {
-    let ctx = db().lock().unwrap();
-    let mut stmt = ctx
-        .prepare("select cmd, args from procs WHERE name = ?1")
-        .unwrap();
-    let mut rows = stmt.query(params!(name)).unwrap();
-    run_by_row(&mut rows);
+
+async fn supervise_one(entry: Entry, healthy: watch::Sender<bool>) {
+    let cfg = &config().supervisor;
+    let check = cfg.health.get(&entry.name);
+    let filename = get_filename_from_path(&entry.cmd).to_string();
+    let mut backoff = cfg.backoff_min;
+
+    loop {
+        if is_proc_running(&filename) {
+            // started outside of us, only take over once it is gone
+            if let Some(check) = check {
+                healthy.send_replace(is_healthy(&check.addr).await);
+            } else {
+                healthy.send_replace(true);
+            }
+            sleep(Duration::from_secs(check.map_or(10, |c| c.interval))).await;
+            continue;
+        }
+
+        let started = Instant::now();
+        match spawn(&entry) {
+            Ok(mut child) => {
+                info!("proc {} started, pid:{:?}", entry.name, child.id());
+                if check.is_none() {
+                    healthy.send_replace(true);
+                }
+                let reason = match check {
+                    Some(check) => tokio::select! {
+                        status = child.wait() => format!("exited with {:?}", status),
+                        _ = unhealthy(&entry.name, check, &healthy) => {
+                            let _ = child.kill().await;
+                            "failed its health check".to_string()
+                        }
+                    },
+                    None => format!("exited with {:?}", child.wait().await),
+                };
+                healthy.send_replace(false);
+                if started.elapsed() > Duration::from_secs(STABLE_SECS) {
+                    backoff = cfg.backoff_min;
+                }
+                error!("proc {} {}, restart in {}s", entry.name, reason, backoff);
+            }
+            Err(e) => error!("exec {} error:{:?}, retry in {}s", entry.cmd, e, backoff),
+        }
+        sleep(Duration::from_secs(backoff)).await;
+        backoff = (backoff * 2).min(cfg.backoff_max);
+    }
 }
 
-pub fn run_procs() {
-    let ctx = db().lock().unwrap();
-    let mut stmt = ctx.prepare("select cmd, args from procs").unwrap();
-    let mut rows = stmt.query(()).unwrap();
-    run_by_row(&mut rows);
+/// Starts a supervisor for every row of `procs`, restarting crashed entries
+/// with exponential backoff, and waits until each of them is healthy or
+/// `supervisor.startup_timeout` has passed.
+pub async fn supervise() {
+    let entries = match get_entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("load procs error:{:?}", e);
+            return;
+        }
+    };
+
+    let mut ready = Vec::new();
+    for entry in entries {
+        let (tx, rx) = watch::channel(false);
+        ready.push((entry.name.clone(), rx));
+        tokio::spawn(supervise_one(entry, tx));
+    }
+
+    let wait = Duration::from_secs(config().supervisor.startup_timeout);
+    let deadline = Instant::now() + wait;
+    for (name, mut rx) in ready {
+        match timeout_at(deadline, rx.wait_for(|healthy| *healthy)).await {
+            Ok(Ok(_)) => info!("proc {} is ready", name),
+            _ => warn!(
+                "proc {} is not ready after {}s, continue anyway",
+                name,
+                wait.as_secs()
+            ),
+        }
+    }
 }
@@ -0,0 +1,52 @@
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+
+/// The kernel truncates `/proc/<pid>/comm` to 15 bytes.
+const COMM_LEN: usize = 15;
+
+fn matches(pid_dir: &Path, name: &str) -> bool {
+    if let Ok(cmdline) = fs::read(pid_dir.join("cmdline")) {
+        let argv0 = cmdline.split(|b| *b == 0).next().unwrap_or_default();
+        let argv0 = String::from_utf8_lossy(argv0);
+        if Path::new(argv0.as_ref())
+            .file_name()
+            .is_some_and(|f| f == name)
+        {
+            return true;
+        }
+    }
+    match fs::read_to_string(pid_dir.join("comm")) {
+        Ok(comm) => {
+            let comm = comm.trim_end_matches('\n');
+            let len = name.len().min(COMM_LEN);
+            name.is_char_boundary(len) && comm == &name[..len]
+        }
+        Err(_) => false,
+    }
+}
+
+fn pids(name: &str) -> Vec<libc::pid_t> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let me = std::process::id() as libc::pid_t;
+    entries
+        .flatten()
+        .filter_map(|e| {
+            let pid: libc::pid_t = e.file_name().to_str()?.parse().ok()?;
+            (pid != me && matches(&e.path(), name)).then_some(pid)
+        })
+        .collect()
+}
+
+pub fn is_running(name: &str) -> bool {
+    !pids(name).is_empty()
+}
+
+/// Puts the child into its own process group so a Ctrl-C on our terminal
+/// doesn't take it down with us, like `DETACHED_PROCESS` on Windows.
+pub fn detach(cmd: &mut Command) {
+    cmd.process_group(0);
+}
@@ -0,0 +1,22 @@
+use std::os::windows::process::CommandExt;
+use std::process::Command;
+use tracing::error;
+
+const DETACHED_PROCESS: u32 = 0x00000008;
+
+pub fn is_running(name: &str) -> bool {
+    let output = match Command::new("tasklist").output() {
+        Ok(output) => output,
+        Err(e) => {
+            error!("tasklist error:{:?}", e);
+            return false;
+        }
+    };
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+    output_str.lines().any(|line| line.contains(name))
+}
+
+pub fn detach(cmd: &mut Command) {
+    cmd.creation_flags(DETACHED_PROCESS);
+}
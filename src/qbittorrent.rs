@@ -0,0 +1,250 @@
+use crate::config::QbittorrentConfig;
+use crate::downloader::{
+    magnet_info_hash, DownloadFile, DownloadState, DownloadStatus, Downloader,
+};
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// How long `add` waits for a torrent without a magnet hash to show up.
+const ADD_LOOKUP_RETRIES: u32 = 10;
+
+#[derive(Deserialize)]
+struct TorrentInfo {
+    hash: String,
+    state: String,
+    /// bytes of the selected files
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    completed: u64,
+}
+
+#[derive(Deserialize)]
+struct TorrentFile {
+    #[serde(default)]
+    index: Option<u32>,
+    name: String,
+    size: u64,
+    progress: f64,
+    /// 0 means the file is not downloaded
+    priority: u8,
+}
+
+/// Client of the qBittorrent WebUI API (v2). Downloads are identified by
+/// their infohash.
+pub struct QBittorrent {
+    client: Client,
+    url: String,
+    username: String,
+    password: Option<String>,
+    poll: Duration,
+}
+
+impl QBittorrent {
+    pub fn new(cfg: &QbittorrentConfig) -> Self {
+        QBittorrent {
+            client: Client::builder()
+                .cookie_store(true)
+                .build()
+                .unwrap_or_default(),
+            url: cfg.url.trim_end_matches('/').to_string(),
+            username: cfg.username.clone(),
+            password: cfg.password.clone(),
+            poll: Duration::from_secs(cfg.poll_secs),
+        }
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!("{}/api/v2/{}", self.url, path)
+    }
+
+    async fn login(&self) -> Result<()> {
+        let rsp = self
+            .client
+            .post(self.api("auth/login"))
+            .header("Referer", &self.url)
+            .form(&[
+                ("username", self.username.as_str()),
+                ("password", self.password.as_deref().unwrap_or_default()),
+            ])
+            .send()
+            .await
+            .map_err(Error::Qbittorrent)?;
+        if rsp.status() == StatusCode::FORBIDDEN {
+            return Err(Error::QbittorrentApi(
+                "login banned after too many failures".to_string(),
+            ));
+        }
+        let text = rsp.text().await.map_err(Error::Qbittorrent)?;
+        if text.trim() != "Ok." {
+            return Err(Error::QbittorrentApi(format!("login failed: {text}")));
+        }
+        debug!("logged in to qbittorrent {}", self.url);
+        Ok(())
+    }
+
+    /// Sends the request built by `req`, logging in once when the session is
+    /// missing or expired.
+    async fn send(&self, req: impl Fn(&Client) -> RequestBuilder) -> Result<Response> {
+        let mut rsp = req(&self.client).send().await.map_err(Error::Qbittorrent)?;
+        if rsp.status() == StatusCode::FORBIDDEN {
+            self.login().await?;
+            rsp = req(&self.client).send().await.map_err(Error::Qbittorrent)?;
+        }
+        rsp.error_for_status().map_err(Error::Qbittorrent)
+    }
+
+    async fn torrents(&self, query: &[(&str, &str)]) -> Result<Vec<TorrentInfo>> {
+        self.send(|c| c.get(self.api("torrents/info")).query(query))
+            .await?
+            .json()
+            .await
+            .map_err(Error::Qbittorrent)
+    }
+
+    /// Posts `hashes` to a torrents endpoint, e.g. pause or delete.
+    async fn post_hashes(
+        &self,
+        path: &str,
+        hash: &str,
+        extra: &[(&str, &str)],
+    ) -> Result<Response> {
+        let mut form = vec![("hashes", hash)];
+        form.extend_from_slice(extra);
+        self.send(|c| c.post(self.api(path)).form(&form)).await
+    }
+}
+
+#[async_trait]
+impl Downloader for QBittorrent {
+    async fn add(&self, uri: &str, dir: &str, _name: Option<&str>) -> Result<String> {
+        // the api doesn't return the hash, tag the torrent to find it again
+        let tag = format!(
+            "bgm-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+        );
+        let form = [("urls", uri), ("savepath", dir), ("tags", tag.as_str())];
+        let text = self
+            .send(|c| c.post(self.api("torrents/add")).form(&form))
+            .await?
+            .text()
+            .await
+            .map_err(Error::Qbittorrent)?;
+        if text.trim() == "Fails." {
+            return Err(Error::QbittorrentApi(format!("add {uri} failed")));
+        }
+
+        if let Some(hash) = magnet_info_hash(uri) {
+            return Ok(hash);
+        }
+        for _ in 0..ADD_LOOKUP_RETRIES {
+            if let Some(t) = self.torrents(&[("tag", &tag)]).await?.into_iter().next() {
+                return Ok(t.hash);
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        Err(Error::QbittorrentApi(format!(
+            "added {uri} but it didn't show up"
+        )))
+    }
+
+    async fn status(&self, id: &str) -> Result<DownloadStatus> {
+        let Some(t) = self.torrents(&[("hashes", id)]).await?.into_iter().next() else {
+            return Ok(DownloadStatus {
+                id: id.to_string(),
+                state: DownloadState::Removed,
+                total: 0,
+                completed: 0,
+                info_hash: Some(id.to_string()),
+                followed_by: None,
+            });
+        };
+        let state = match t.state.as_str() {
+            "error" | "missingFiles" => DownloadState::Failed(t.state.clone()),
+            "pausedDL" | "stoppedDL" => DownloadState::Paused,
+            "queuedDL" | "checkingResumeData" | "moving" => DownloadState::Waiting,
+            "uploading" | "stalledUP" | "queuedUP" | "forcedUP" | "checkingUP" | "pausedUP"
+            | "stoppedUP" => DownloadState::Complete,
+            // downloading, metaDL, stalledDL, forcedDL, checkingDL, allocating, ...
+            _ => DownloadState::Active,
+        };
+        Ok(DownloadStatus {
+            id: t.hash.clone(),
+            state,
+            total: t.size,
+            completed: t.completed,
+            info_hash: Some(t.hash),
+            followed_by: None,
+        })
+    }
+
+    async fn pause(&self, id: &str) -> Result<()> {
+        // renamed to stop in qBittorrent 5
+        match self.post_hashes("torrents/pause", id, &[]).await {
+            Err(Error::Qbittorrent(e)) if e.status() == Some(StatusCode::NOT_FOUND) => {
+                self.post_hashes("torrents/stop", id, &[]).await?;
+            }
+            r => {
+                r?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, id: &str) -> Result<()> {
+        self.post_hashes("torrents/delete", id, &[("deleteFiles", "false")])
+            .await?;
+        Ok(())
+    }
+
+    async fn files(&self, id: &str) -> Result<Vec<DownloadFile>> {
+        let files: Vec<TorrentFile> = self
+            .send(|c| c.get(self.api("torrents/files")).query(&[("hash", id)]))
+            .await?
+            .json()
+            .await
+            .map_err(Error::Qbittorrent)?;
+        Ok(files
+            .into_iter()
+            .enumerate()
+            .map(|(i, f)| DownloadFile {
+                index: f.index.unwrap_or(i as u32),
+                path: f.name,
+                length: f.size,
+                completed: (f.size as f64 * f.progress) as u64,
+                selected: f.priority > 0,
+            })
+            .collect())
+    }
+
+    async fn select(&self, id: &str, indexes: &[u32]) -> Result<()> {
+        let (wanted, unwanted): (Vec<u32>, Vec<u32>) = self
+            .files(id)
+            .await?
+            .iter()
+            .map(|f| f.index)
+            .partition(|i| indexes.contains(i));
+        for (ids, priority) in [(wanted, "1"), (unwanted, "0")] {
+            if ids.is_empty() {
+                continue;
+            }
+            let ids: Vec<String> = ids.iter().map(u32::to_string).collect();
+            let ids = ids.join("|");
+            let form = [("hash", id), ("id", ids.as_str()), ("priority", priority)];
+            self.send(|c| c.post(self.api("torrents/filePrio")).form(&form))
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.poll
+    }
+}
@@ -0,0 +1,208 @@
+use crate::config::{config, FeedConfig};
+use crate::downloader::magnet_info_hash;
+use crate::error::{Error, Result};
+use crate::source::{self, Release, TorrentSource};
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use roxmltree::Node;
+use tracing::error;
+
+#[cfg(test)]
+mod tests;
+
+/// Where the fields of a release live in an item, as paths of element names
+/// relative to the item, optionally ending in `@attribute`.
+#[derive(Debug, Clone)]
+struct Mapping {
+    /// path of the items from the root element, any item or entry if unset
+    item: Option<String>,
+    title: Vec<String>,
+    /// a magnet or a .torrent url
+    link: Vec<String>,
+    info_hash: Vec<String>,
+    pub_date: Vec<String>,
+    size: Vec<String>,
+}
+
+impl Default for Mapping {
+    /// RSS 2.0 and Atom.
+    fn default() -> Self {
+        let paths = |p: &[&str]| p.iter().map(|s| s.to_string()).collect();
+        Mapping {
+            item: None,
+            title: paths(&["title"]),
+            link: paths(&["enclosure/@url", "link/@href", "link"]),
+            info_hash: Vec::new(),
+            pub_date: paths(&["pubDate", "published", "updated"]),
+            size: paths(&["enclosure/@length"]),
+        }
+    }
+}
+
+impl Mapping {
+    fn from_config(cfg: &FeedConfig) -> Self {
+        let mut mapping = Mapping {
+            item: cfg.item.clone(),
+            ..Default::default()
+        };
+        let set = |field: &mut Vec<String>, path: &Option<String>| {
+            if let Some(path) = path {
+                *field = vec![path.clone()];
+            }
+        };
+        set(&mut mapping.title, &cfg.title);
+        set(&mut mapping.link, &cfg.link);
+        set(&mut mapping.info_hash, &cfg.info_hash);
+        set(&mut mapping.pub_date, &cfg.pub_date);
+        set(&mut mapping.size, &cfg.size);
+        mapping
+    }
+}
+
+/// Value at `path` below `node`, e.g. `title`, `enclosure/@url` or
+/// `torrent/contentLength`. Steps match local names, namespaces aside.
+fn select<'a>(node: Node<'a, '_>, path: &str) -> Option<&'a str> {
+    let mut node = node;
+    for step in path.split('/').filter(|s| !s.is_empty()) {
+        if let Some(attr) = step.strip_prefix('@') {
+            return node.attribute(attr);
+        }
+        node = node
+            .children()
+            .find(|c| c.is_element() && c.tag_name().name() == step)?;
+    }
+    node.text().map(str::trim).filter(|t| !t.is_empty())
+}
+
+fn select_any<'a>(node: Node<'a, '_>, paths: &[String]) -> Option<&'a str> {
+    paths.iter().find_map(|p| select(node, p))
+}
+
+/// RFC 2822 as in RSS, RFC 3339 as in Atom, or a local time without offset.
+fn parse_date(s: &str) -> Result<NaiveDateTime> {
+    if let Ok(t) = DateTime::parse_from_rfc2822(s) {
+        return Ok(t.with_timezone(&Local).naive_local());
+    }
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.with_timezone(&Local).naive_local());
+    }
+    let t = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S"))?;
+    Local
+        .from_local_datetime(&t)
+        .earliest()
+        .map(|t| t.naive_local())
+        .ok_or_else(|| Error::parse(format!("invalid date {s}")))
+}
+
+fn parse_item(item: Node, m: &Mapping) -> Result<Release> {
+    let title =
+        select_any(item, &m.title).ok_or_else(|| Error::parse("feed item without title"))?;
+    let link = select_any(item, &m.link)
+        .ok_or_else(|| Error::parse(format!("feed item {title} without link")))?;
+    let info_hash = select_any(item, &m.info_hash)
+        .map(str::to_ascii_lowercase)
+        .or_else(|| magnet_info_hash(link))
+        .or_else(|| source::hash_in_link(link))
+        .unwrap_or_default();
+    let pub_date = select_any(item, &m.pub_date)
+        .ok_or_else(|| Error::parse(format!("feed item {title} without date")))?;
+    Ok(Release {
+        title: title.to_string(),
+        magnet: link.to_string(),
+        info_hash,
+        size: select_any(item, &m.size)
+            .and_then(source::parse_size)
+            .unwrap_or_default(),
+        publish_time: parse_date(pub_date)?,
+        seeders: None,
+        files: Vec::new(),
+    })
+}
+
+fn parse_feed(xml: &str, m: &Mapping) -> Result<Vec<Release>> {
+    let doc = roxmltree::Document::parse(xml).map_err(Error::parse)?;
+    let items: Vec<Node> = match &m.item {
+        Some(path) => {
+            let steps: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+            let Some((name, parents)) = steps.split_last() else {
+                return Err(Error::parse("empty item path"));
+            };
+            let mut parent = doc.root_element();
+            for step in parents {
+                parent = parent
+                    .children()
+                    .find(|c| c.is_element() && c.tag_name().name() == *step)
+                    .ok_or_else(|| Error::parse(format!("no {step} in feed")))?;
+            }
+            parent
+                .children()
+                .filter(|c| c.is_element() && c.tag_name().name() == *name)
+                .collect()
+        }
+        None => doc
+            .descendants()
+            .filter(|n| n.is_element() && matches!(n.tag_name().name(), "item" | "entry"))
+            .collect(),
+    };
+    Ok(items
+        .into_iter()
+        .filter_map(|item| {
+            parse_item(item, m)
+                .map_err(|e| error!("skip feed item: {}", e))
+                .ok()
+        })
+        .collect())
+}
+
+/// Any RSS 2.0 or Atom feed, read with the mappings of a `[feeds.<name>]`
+/// table or the defaults.
+pub struct Rss {
+    url: Option<String>,
+    /// name of the `[feeds]` table
+    profile: Option<String>,
+}
+
+impl Rss {
+    /// A feed at `url` in plain RSS 2.0 or Atom.
+    pub fn url(url: &str) -> Self {
+        Rss {
+            url: Some(url.to_string()),
+            profile: None,
+        }
+    }
+
+    /// The feed described by `[feeds.<name>]` of the config.
+    pub fn profile(name: &str) -> Self {
+        Rss {
+            url: None,
+            profile: Some(name.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl TorrentSource for Rss {
+    async fn releases(&self, _since: &NaiveDateTime) -> Result<Vec<Release>> {
+        let (url, mapping) = match &self.profile {
+            Some(name) => {
+                let cfg = config()
+                    .feeds
+                    .get(name)
+                    .ok_or_else(|| Error::NotFound(format!("feed `{name}` in [feeds]")))?;
+                (cfg.url.as_str(), Mapping::from_config(cfg))
+            }
+            None => (self.url.as_deref().unwrap_or_default(), Mapping::default()),
+        };
+        let xml = source::client()?
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(Error::Source)?
+            .text()
+            .await
+            .map_err(Error::Source)?;
+        parse_feed(&xml, &mapping)
+    }
+}
@@ -0,0 +1,101 @@
+use super::*;
+
+fn local(rfc3339: &str) -> NaiveDateTime {
+    DateTime::parse_from_rfc3339(rfc3339)
+        .unwrap()
+        .with_timezone(&Local)
+        .naive_local()
+}
+
+fn naive_local(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+}
+
+#[test]
+fn rss2() {
+    let releases = parse_feed(include_str!("rss2.xml"), &Mapping::default()).unwrap();
+    // the items without a link or a date are skipped
+    assert_eq!(releases.len(), 3);
+
+    let r = &releases[0];
+    assert_eq!(r.title, "[SubsPlease] Sousou no Frieren - 03 (1080p)");
+    // the enclosure comes before the link of the page
+    assert!(r.magnet.starts_with("magnet:?xt=urn:btih:0A1B2C3D"));
+    assert_eq!(r.info_hash, "0a1b2c3d4e5f60718293a4b5c6d7e8f901234567");
+    assert_eq!(r.size, 1503238553);
+    assert_eq!(r.publish_time, local("2023-10-13T14:32:05Z"));
+
+    let r = &releases[1];
+    assert_eq!(
+        r.magnet,
+        "https://tracker.example/download/FFEEDDCCBBAA99887766554433221100FFEEDDCC.torrent"
+    );
+    // told by the name of the .torrent
+    assert_eq!(r.info_hash, "ffeeddccbbaa99887766554433221100ffeeddcc");
+    assert_eq!(r.size, 0);
+
+    let r = &releases[2];
+    assert_eq!(r.magnet, "https://tracker.example/download/5.torrent");
+    assert_eq!(r.info_hash, "");
+    assert_eq!(r.hash(), r.magnet);
+}
+
+#[test]
+fn atom() {
+    let releases = parse_feed(include_str!("atom.xml"), &Mapping::default()).unwrap();
+    assert_eq!(releases.len(), 2);
+
+    let r = &releases[0];
+    assert_eq!(r.title, "[LoliHouse] Dungeon Meshi - 07 [1080p]");
+    assert_eq!(r.info_hash, "0c0c35c6bdce0ada041ede0e618bcf67131e15c7");
+    // published comes before updated
+    assert_eq!(r.publish_time, local("2024-02-15T13:30:00Z"));
+
+    let r = &releases[1];
+    assert_eq!(r.magnet, "https://tracker.example/t/8.torrent");
+    assert_eq!(r.publish_time, local("2024-02-22T13:30:00Z"));
+}
+
+#[test]
+fn mapped() {
+    let cfg: FeedConfig = toml::from_str(
+        r#"
+        url = "https://tracker.example/api"
+        item = "torrents/torrent"
+        title = "name"
+        link = "download/@href"
+        info_hash = "meta/@hash"
+        size = "meta/@bytes"
+        pub_date = "added"
+        "#,
+    )
+    .unwrap();
+    let releases = parse_feed(include_str!("custom.xml"), &Mapping::from_config(&cfg)).unwrap();
+    // the torrent without a download link is skipped, the item outside the
+    // path isn't looked at
+    assert_eq!(releases.len(), 2);
+
+    let r = &releases[0];
+    assert_eq!(r.title, "[Group] Name - 01 [1080p]");
+    assert_eq!(r.magnet, "https://tracker.example/dl/1.torrent");
+    assert_eq!(r.info_hash, "c0ffee00112233445566778899aabbccddeeff00");
+    assert_eq!(r.size, 734003200);
+    assert_eq!(r.publish_time, naive_local("2024-04-06 21:00:00"));
+
+    let r = &releases[1];
+    // no hash attribute, nor one in the link
+    assert_eq!(r.info_hash, "");
+    assert_eq!(r.size, 1610612736);
+    assert_eq!(r.publish_time, naive_local("2024-04-13 21:00:00"));
+
+    // without the path only the <item> is looked at, and it has no link
+    let cfg = FeedConfig { item: None, ..cfg };
+    let releases = parse_feed(include_str!("custom.xml"), &Mapping::from_config(&cfg)).unwrap();
+    assert!(releases.is_empty());
+
+    let cfg = FeedConfig {
+        item: Some("missing/torrent".to_string()),
+        ..cfg
+    };
+    assert!(parse_feed(include_str!("custom.xml"), &Mapping::from_config(&cfg)).is_err());
+}
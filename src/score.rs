@@ -0,0 +1,80 @@
+use crate::config::ScoreConfig;
+use crate::source::Release;
+use crate::title::{self, ParsedRelease};
+
+#[cfg(test)]
+mod tests;
+
+/// How well a release fits the profile, fields compare in order and higher
+/// is better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Score {
+    pub group: usize,
+    pub resolution: usize,
+    pub subtitles: usize,
+    pub codec: usize,
+    pub seeders: u32,
+}
+
+/// `list.len()` for the first entry down to 1 for the last, 0 if unlisted.
+fn rank<T>(list: &[T], found: impl Fn(&T) -> bool) -> usize {
+    list.iter().position(found).map_or(0, |i| list.len() - i)
+}
+
+/// Whether the release is within the size and seeder bounds.
+pub fn acceptable(release: &Release, profile: &ScoreConfig) -> bool {
+    let size = Some(release.size).filter(|s| *s > 0);
+    size.is_none_or(|s| profile.min_size().is_none_or(|min| s >= min))
+        && size.is_none_or(|s| profile.max_size().is_none_or(|max| s <= max))
+        && release.seeders.is_none_or(|s| s >= profile.min_seeders)
+}
+
+pub fn score(release: &Release, parsed: &ParsedRelease, profile: &ScoreConfig) -> Score {
+    Score {
+        seeders: release.seeders.unwrap_or_default(),
+        ..rank_title(parsed, profile)
+    }
+}
+
+/// The score of what the title tells, seeders aside.
+fn rank_title(parsed: &ParsedRelease, profile: &ScoreConfig) -> Score {
+    let group = parsed.group.as_deref().unwrap_or_default().to_lowercase();
+    Score {
+        group: rank(&profile.groups, |g| {
+            !group.is_empty() && group.contains(&g.to_lowercase())
+        }),
+        resolution: rank(&profile.resolutions, |r| Some(*r) == parsed.resolution),
+        subtitles: rank(&profile.subtitles, |l| parsed.subtitles.contains(l)),
+        codec: rank(&profile.codecs, |c| Some(*c) == parsed.codec),
+        seeders: 0,
+    }
+}
+
+/// Whether `candidate` is strictly better than the release titled `current`:
+/// a later version from the same group, or a higher score. Seeders don't
+/// count, they come and go.
+pub fn is_upgrade(current: &str, candidate: &Release, profile: &ScoreConfig) -> bool {
+    let (current, new) = (title::parse(current), title::parse(&candidate.title));
+    if current.group.is_some()
+        && current.group == new.group
+        && new.version.unwrap_or(1) > current.version.unwrap_or(1)
+    {
+        return true;
+    }
+    rank_title(&new, profile) > rank_title(&current, profile)
+}
+
+/// The best acceptable release of `candidates`, the earliest published one
+/// of equal scores and the first of those.
+pub fn best<'a>(candidates: &[&'a Release], profile: &ScoreConfig) -> Option<(&'a Release, Score)> {
+    let mut best: Option<(&Release, Score)> = None;
+    for release in candidates.iter().filter(|r| acceptable(r, profile)) {
+        let score = score(release, &title::parse(&release.title), profile);
+        if best
+            .is_none_or(|(b, s)| score > s || (score == s && release.publish_time < b.publish_time))
+        {
+            best = Some((release, score));
+        }
+    }
+    best
+}
@@ -0,0 +1,192 @@
+use super::*;
+use crate::title::{Codec, Language};
+use chrono::NaiveDateTime;
+
+fn release(title: &str) -> Release {
+    Release {
+        title: title.to_string(),
+        magnet: String::new(),
+        info_hash: String::new(),
+        size: 0,
+        publish_time: NaiveDateTime::default(),
+        seeders: Some(100),
+        files: Vec::new(),
+    }
+}
+
+fn profile() -> ScoreConfig {
+    ScoreConfig {
+        groups: vec!["LoliHouse".to_string(), "SubsPlease".to_string()],
+        resolutions: vec![1080, 720],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn same_group_version() {
+    let profile = profile();
+    let current = "[SubsPlease] Sousou no Frieren - 03 (1080p)";
+    assert!(is_upgrade(
+        current,
+        &release("[SubsPlease] Sousou no Frieren - 03v2 (1080p)"),
+        &profile
+    ));
+    assert!(is_upgrade(
+        "[SubsPlease] Sousou no Frieren - 03v2 (1080p)",
+        &release("[SubsPlease] Sousou no Frieren - 03v3 (720p)"),
+        &profile
+    ));
+    // a v2 of another group is no later version of ours
+    assert!(!is_upgrade(
+        current,
+        &release("[Erai-raws] Sousou no Frieren - 03v2 (1080p)"),
+        &profile
+    ));
+    assert!(!is_upgrade(
+        "[SubsPlease] Sousou no Frieren - 03v2 (1080p)",
+        &release(current),
+        &profile
+    ));
+}
+
+#[test]
+fn higher_rank() {
+    let profile = profile();
+    assert!(is_upgrade(
+        "[SubsPlease] Sousou no Frieren - 03 (1080p)",
+        &release("[LoliHouse] Sousou no Frieren - 03 [1080p]"),
+        &profile
+    ));
+    assert!(is_upgrade(
+        "[SubsPlease] Sousou no Frieren - 03 (720p)",
+        &release("[SubsPlease] Sousou no Frieren - 03 (1080p)"),
+        &profile
+    ));
+    assert!(!is_upgrade(
+        "[LoliHouse] Sousou no Frieren - 03 [1080p]",
+        &release("[SubsPlease] Sousou no Frieren - 03 (1080p)"),
+        &profile
+    ));
+}
+
+#[test]
+fn equal_rank() {
+    let profile = profile();
+    let current = "[SubsPlease] Sousou no Frieren - 03 (1080p)";
+    assert!(!is_upgrade(current, &release(current), &profile));
+    // seeders don't count
+    let mut seeded = release("[SubsPlease] Sousou no Frieren - 03 (1080p) [HEVC]");
+    seeded.seeders = Some(1000);
+    assert!(!is_upgrade(current, &seeded, &profile));
+    assert!(!is_upgrade(
+        "[Erai-raws] Sousou no Frieren - 03 (1080p)",
+        &release("[Commie] Sousou no Frieren - 03 (1080p)"),
+        &profile
+    ));
+}
+
+fn ranked() -> ScoreConfig {
+    ScoreConfig {
+        groups: vec!["LoliHouse".to_string(), "ANi".to_string()],
+        resolutions: vec![1080, 720],
+        subtitles: vec![Language::Chs, Language::Cht],
+        codecs: vec![Codec::H265, Codec::H264],
+        ..Default::default()
+    }
+}
+
+fn best_of<'a>(candidates: &[&'a Release], profile: &ScoreConfig) -> Option<&'a str> {
+    best(candidates, profile).map(|(r, _)| r.title.as_str())
+}
+
+#[test]
+fn ordering() {
+    let profile = ranked();
+    let cases = [
+        // group first, then resolution, subtitles and codec
+        (
+            "[LoliHouse] Name - 07 [WebRip 720p HEVC-10bit AAC]",
+            "[ANi] Name - 07 [1080P][Baha][WEB-DL][AAC AVC][CHT]",
+        ),
+        (
+            "[ANi] Name - 07 [1080P][WEB-DL][AAC AVC]",
+            "[ANi] Name - 07 [720P][WEB-DL][AAC AVC]",
+        ),
+        (
+            "[Group] Name - 07 [1080p][简体]",
+            "[Group] Name - 07 [1080p][繁體]",
+        ),
+        (
+            "[Group] Name - 07 [1080p][HEVC]",
+            "[Group] Name - 07 [1080p][AVC]",
+        ),
+        // unlisted ranks last
+        ("[Group] Name - 07 [720p]", "[Group] Name - 07 [480p]"),
+        ("[ANi] Name - 07 [480p]", "[Unknown] Name - 07 [1080p]"),
+        ("[Group] Name - 07 [AVC]", "[Group] Name - 07 [AV1]"),
+    ];
+    for (better, worse) in cases {
+        let (better, worse) = (release(better), release(worse));
+        assert_eq!(
+            best_of(&[&worse, &better], &profile),
+            Some(better.title.as_str()),
+            "{}",
+            better.title
+        );
+        assert_eq!(
+            best_of(&[&better, &worse], &profile),
+            Some(better.title.as_str()),
+            "{}",
+            better.title
+        );
+    }
+}
+
+#[test]
+fn bounds() {
+    let profile = ScoreConfig {
+        min_size: Some("100MiB".to_string()),
+        max_size: Some("2GiB".to_string()),
+        min_seeders: 5,
+        ..Default::default()
+    };
+    let sized = |size: u64, seeders: Option<u32>| Release {
+        size,
+        seeders,
+        ..release("[Group] Name - 07 [1080p]")
+    };
+    assert!(acceptable(&sized(500 << 20, Some(5)), &profile));
+    assert!(!acceptable(&sized(50 << 20, Some(10)), &profile));
+    assert!(!acceptable(&sized(3 << 30, Some(10)), &profile));
+    assert!(!acceptable(&sized(500 << 20, Some(4)), &profile));
+    // unknown size or seeders don't count against a release
+    assert!(acceptable(&sized(0, None), &profile));
+    assert_eq!(
+        best(&[&sized(50 << 20, Some(10))], &profile).map(|(r, _)| r.size),
+        None
+    );
+}
+
+#[test]
+fn ties() {
+    let profile = ranked();
+    let at = |title: &str, minute: u32| Release {
+        publish_time: NaiveDateTime::default() + chrono::TimeDelta::minutes(minute.into()),
+        ..release(title)
+    };
+    let early = at("[Group] Name - 07 [1080p] a", 1);
+    let late = at("[Group] Name - 07 [1080p] b", 2);
+    assert_eq!(
+        best_of(&[&late, &early], &profile),
+        Some(early.title.as_str())
+    );
+    assert_eq!(
+        best_of(&[&early, &late], &profile),
+        Some(early.title.as_str())
+    );
+    let twin = at("[Group] Name - 07 [1080p] c", 1);
+    assert_eq!(
+        best_of(&[&early, &twin], &profile),
+        Some(early.title.as_str())
+    );
+}
@@ -0,0 +1,200 @@
+use crate::config::{config, FeedConfig};
+use crate::dmhy::Dmhy;
+use crate::error::{Error, Result};
+use crate::history;
+use crate::mikan::{self, Mikan};
+use crate::moe::Moe;
+use crate::nyaa::Nyaa;
+use crate::rss::Rss;
+use crate::title;
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDateTime};
+use reqwest::Client;
+use std::collections::HashMap;
+
+/// A torrent published by a source, in the shape every source agrees on.
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub title: String,
+    /// a magnet, or the url of a .torrent for sources without magnets
+    pub magnet: String,
+    /// lowercase hex, empty when the source doesn't tell
+    pub info_hash: String,
+    /// in bytes, 0 when unknown
+    pub size: u64,
+    /// local time
+    pub publish_time: NaiveDateTime,
+    pub seeders: Option<u32>,
+    /// paths inside the torrent, when the source lists them
+    pub files: Vec<String>,
+}
+
+impl Release {
+    /// Name of the first file of the torrent, without its folders.
+    pub fn file_name(&self) -> Option<&str> {
+        self.files.first()?.rsplit('/').next()
+    }
+
+    /// Name the download is expected to take: its first file, or its title
+    /// when the source doesn't list the files.
+    pub fn download_name(&self) -> &str {
+        self.file_name().unwrap_or(&self.title)
+    }
+
+    /// Key of the release in the torrent history: its infohash, taken from
+    /// the magnet when the source doesn't tell, or the uri itself.
+    pub fn hash(&self) -> String {
+        if self.info_hash.is_empty() {
+            history::uri_hash(&self.magnet)
+        } else {
+            self.info_hash.clone()
+        }
+    }
+
+    /// Episodes the release holds: the range of a batch title, those of its
+    /// files when the title tells none, or the one of the title.
+    pub fn episodes(&self) -> Vec<u32> {
+        let parsed = title::parse(&self.title);
+        if let Some((first, last)) = parsed.batch {
+            return (first..=last).collect();
+        }
+        if parsed.episode.is_none() && self.files.len() > 1 {
+            let mut episodes: Vec<u32> = self
+                .files
+                .iter()
+                .filter_map(|f| title::file_episode(f))
+                .collect();
+            episodes.sort_unstable();
+            episodes.dedup();
+            return episodes;
+        }
+        parsed.episode.into_iter().collect()
+    }
+
+    /// Positions of the files of `episodes` in a release holding several
+    /// episodes, empty for all of them.
+    pub fn files_of(&self, episodes: &[u32]) -> Vec<u32> {
+        if self.episodes().len() < 2 {
+            return Vec::new();
+        }
+        self.files
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| title::file_episode(f).is_some_and(|e| episodes.contains(&e)))
+            .map(|(i, _)| i as u32)
+            .collect()
+    }
+}
+
+/// Somewhere releases are published, e.g. a tracker's feed.
+#[async_trait]
+pub trait TorrentSource: Send + Sync {
+    /// Releases published since `since`. Sources may return older ones too.
+    async fn releases(&self, since: &NaiveDateTime) -> Result<Vec<Release>>;
+}
+
+/// Builds the source described by `spec`, `<kind>` or `<kind>:<argument>`:
+///
+/// - `moe`: the latest torrents of bangumi.moe
+/// - `moe:<keywords>`, `moe:tag=<id>[,<id>]`: a bangumi.moe search, crawling
+///   the latest torrents when it fails
+/// - `nyaa`, `nyaa:<query>`: the nyaa.si feed, optionally searched
+/// - `mikan`: the Mikan Project feed of `mikan.token`
+/// - `mikan:<bangumi id>[/<subgroup id>]`, `mikan:<url>`: a Mikan bangumi feed
+/// - `dmhy:<keyword>`, `dmhy:keyword=..&team_id=..`: a share.dmhy.org search
+/// - `rss:<url>`: any RSS 2.0 or Atom feed
+/// - `feed:<name>`: the feed and field mappings of `[feeds.<name>]`
+pub fn parse(spec: &str) -> Result<Box<dyn TorrentSource>> {
+    parse_in(spec, &config().feeds)
+}
+
+/// [`parse`] against `feeds` rather than those of the loaded config.
+pub fn parse_in(spec: &str, feeds: &HashMap<String, FeedConfig>) -> Result<Box<dyn TorrentSource>> {
+    let (kind, arg) = match spec.split_once(':') {
+        Some((kind, arg)) => (kind, Some(arg)),
+        None => (spec, None),
+    };
+    match (kind, arg) {
+        ("moe", None) => Ok(Box::new(Moe::latest())),
+        ("moe", Some(arg)) => Ok(Box::new(Moe::parse(arg)?)),
+        ("nyaa", query) => Ok(Box::new(Nyaa::new(query.unwrap_or_default()))),
+        ("mikan", None) => Ok(Box::new(Mikan::new(mikan::Feed::MyBangumi))),
+        ("mikan", Some(feed)) => Ok(Box::new(Mikan::new(mikan::Feed::parse(feed)?))),
+        ("dmhy", query) => Ok(Box::new(Dmhy::parse(query.unwrap_or_default())?)),
+        ("rss", Some(url)) if reqwest::Url::parse(url).is_ok() => Ok(Box::new(Rss::url(url))),
+        ("feed", Some(name)) if feeds.contains_key(name) => Ok(Box::new(Rss::profile(name))),
+        ("feed", Some(name)) => Err(Error::NotFound(format!("[feeds.{name}] of `{spec}`"))),
+        _ => Err(Error::parse(format!("unknown torrent source `{spec}`"))),
+    }
+}
+
+/// http client for torrent sites, going through the configured proxy.
+pub fn client() -> Result<Client> {
+    let mut builder = Client::builder().user_agent(&config().http.user_agent);
+    if let Some(proxy) = &config().http.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy).map_err(Error::Source)?);
+    }
+    builder.build().map_err(Error::Source)
+}
+
+/// Bytes of a human readable size like `361.8 MB` or `1.2 GiB`.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: f64 = num.parse().ok()?;
+    let exp = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" | "KI" => 1,
+        "M" | "MI" => 2,
+        "G" | "GI" => 3,
+        "T" | "TI" => 4,
+        _ => return None,
+    };
+    // sites mean 1024 whether they write MB or MiB
+    Some((num * 1024f64.powi(exp)) as u64)
+}
+
+/// A magnet of `info_hash` named `title`, announced to `trackers`.
+pub fn magnet(info_hash: &str, title: &str, trackers: &[&str]) -> String {
+    let mut url = reqwest::Url::parse("magnet:").expect("valid url");
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("dn", title);
+        for tr in trackers {
+            query.append_pair("tr", tr);
+        }
+    }
+    // the urn must not be escaped
+    format!(
+        "magnet:?xt=urn:btih:{}&{}",
+        info_hash,
+        url.query().unwrap_or_default()
+    )
+}
+
+/// The 40 hex digits naming the torrent in a link like
+/// `/Download/20231006/<hash>.torrent` or `/Home/Episode/<hash>`.
+pub fn hash_in_link(link: &str) -> Option<String> {
+    let name = link.rsplit('/').next()?;
+    let hash = name.strip_suffix(".torrent").unwrap_or(name);
+    (hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| hash.to_ascii_lowercase())
+}
+
+/// Text of the first child element of `node` named `name`, namespace aside.
+pub fn child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|c| c.is_element() && c.tag_name().name() == name)
+        .and_then(|c| c.text())
+        .map(str::trim)
+}
+
+/// An RSS `pubDate` in local time.
+pub fn parse_pub_date(s: &str) -> Result<NaiveDateTime> {
+    Ok(DateTime::parse_from_rfc2822(s.trim())?
+        .with_timezone(&Local)
+        .naive_local())
+}
@@ -0,0 +1,159 @@
+use clap::ValueEnum;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use std::fmt::{self, Debug, Display};
+use tracing::error;
+
+#[cfg(test)]
+mod tests;
+
+/// Lifecycle of a `task` row, stored as its integer discriminant.
+///
+/// ```text
+/// Pending --exec_time reached--> Ready --matched & sent--> Downloading --finished--> Done
+///    ^                            | ^                           | ^                   |
+///    |                            | \---- failed or removed ----/ \--better release---/
+///    |                            \--invalid regex--> BadRegex
+///    \-------- retry, from BadRegex or Done --------/
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[repr(u8)]
+pub enum TaskState {
+    /// waiting for its `exec_time`
+    Pending = 0,
+    Done = 1,
+    /// looking for a matching torrent
+    Ready = 2,
+    /// handed over to the downloader
+    Downloading = 3,
+    BadRegex = 4,
+}
+
+/// Lifecycle of a `bgm` row, stored as its integer discriminant.
+///
+/// ```text
+/// New --tasks generated--> Scheduled
+///  ^ \--bad start_date or weekday--> Invalid
+///  \------------- edited -------------/
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BgmState {
+    New = 0,
+    Scheduled = 1,
+    Invalid = 2,
+}
+
+pub trait Transition: Copy + PartialEq + Debug {
+    fn allowed(self, to: Self) -> bool;
+
+    /// Moves to `to` if the transition table allows it, otherwise logs the
+    /// attempt and leaves the state untouched.
+    fn transition(&mut self, to: Self, owner: impl Display) -> bool {
+        if *self == to {
+            return true;
+        }
+        if !self.allowed(to) {
+            error!("{owner}: illegal state transition {:?} -> {:?}", self, to);
+            return false;
+        }
+        *self = to;
+        true
+    }
+}
+
+impl Transition for TaskState {
+    fn allowed(self, to: Self) -> bool {
+        use TaskState::*;
+        matches!(
+            (self, to),
+            (Pending, Ready)
+                | (Ready, Downloading)
+                | (Ready, BadRegex)
+                | (Downloading, Done)
+                | (Downloading, Ready)
+                | (Done, Downloading)
+                | (BadRegex, Pending)
+                | (Done, Pending)
+        )
+    }
+}
+
+impl Transition for BgmState {
+    fn allowed(self, to: Self) -> bool {
+        use BgmState::*;
+        matches!(
+            (self, to),
+            (New, Scheduled) | (New, Invalid) | (Invalid, New)
+        )
+    }
+}
+
+impl Display for TaskState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            TaskState::Pending => "pending",
+            TaskState::Done => "done",
+            TaskState::Ready => "ready",
+            TaskState::Downloading => "downloading",
+            TaskState::BadRegex => "bad-regex",
+        })
+    }
+}
+
+impl Display for BgmState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            BgmState::New => "new",
+            BgmState::Scheduled => "scheduled",
+            BgmState::Invalid => "invalid",
+        })
+    }
+}
+
+impl TryFrom<i64> for TaskState {
+    type Error = i64;
+
+    fn try_from(v: i64) -> Result<Self, Self::Error> {
+        match v {
+            0 => Ok(TaskState::Pending),
+            1 => Ok(TaskState::Done),
+            2 => Ok(TaskState::Ready),
+            3 => Ok(TaskState::Downloading),
+            4 => Ok(TaskState::BadRegex),
+            _ => Err(v),
+        }
+    }
+}
+
+impl TryFrom<i64> for BgmState {
+    type Error = i64;
+
+    fn try_from(v: i64) -> Result<Self, Self::Error> {
+        match v {
+            0 => Ok(BgmState::New),
+            1 => Ok(BgmState::Scheduled),
+            2 => Ok(BgmState::Invalid),
+            _ => Err(v),
+        }
+    }
+}
+
+macro_rules! impl_sql {
+    ($t:ty) => {
+        impl ToSql for $t {
+            fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+                Ok(ToSqlOutput::from(*self as u8))
+            }
+        }
+
+        impl FromSql for $t {
+            fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+                let v = value.as_i64()?;
+                <$t>::try_from(v).map_err(FromSqlError::OutOfRange)
+            }
+        }
+    };
+}
+
+impl_sql!(TaskState);
+impl_sql!(BgmState);
@@ -0,0 +1,81 @@
+use super::*;
+use rusqlite::Connection;
+use TaskState::*;
+
+const TASK_STATES: [TaskState; 5] = [Pending, Done, Ready, Downloading, BadRegex];
+const BGM_STATES: [BgmState; 3] = [BgmState::New, BgmState::Scheduled, BgmState::Invalid];
+
+#[test]
+fn task_transitions() {
+    let allowed = [
+        (Pending, Ready),
+        (Ready, Downloading),
+        (Ready, BadRegex),
+        (Downloading, Done),
+        (Downloading, Ready),
+        // a better release replaces the done one
+        (Done, Downloading),
+        (BadRegex, Pending),
+        (Done, Pending),
+    ];
+    for from in TASK_STATES {
+        for to in TASK_STATES {
+            let mut state = from;
+            let legal = from == to || allowed.contains(&(from, to));
+            assert_eq!(state.transition(to, "task:1"), legal, "{from:?} -> {to:?}");
+            // an illegal transition leaves the state alone
+            assert_eq!(state, if legal { to } else { from }, "{from:?} -> {to:?}");
+        }
+    }
+    for (from, to) in [
+        (Pending, Done),
+        (Pending, Downloading),
+        (Done, Ready),
+        (BadRegex, Downloading),
+        (Downloading, Pending),
+    ] {
+        assert!(!from.allowed(to), "{from:?} -> {to:?}");
+    }
+}
+
+#[test]
+fn bgm_transitions() {
+    use BgmState::*;
+    let allowed = [(New, Scheduled), (New, Invalid), (Invalid, New)];
+    for from in BGM_STATES {
+        for to in BGM_STATES {
+            let mut state = from;
+            let legal = from == to || allowed.contains(&(from, to));
+            assert_eq!(state.transition(to, "bgm:1"), legal, "{from:?} -> {to:?}");
+            assert_eq!(state, if legal { to } else { from }, "{from:?} -> {to:?}");
+        }
+    }
+    assert!(!Scheduled.allowed(New));
+    assert!(!Invalid.allowed(Scheduled));
+}
+
+#[test]
+fn sql_round_trip() {
+    let ctx = Connection::open_in_memory().unwrap();
+    for state in TASK_STATES {
+        let (v, back): (i64, TaskState) = ctx
+            .query_row("SELECT ?1, ?1", [state], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(v, state as i64);
+        assert_eq!(back, state);
+    }
+    for state in BGM_STATES {
+        let back: BgmState = ctx
+            .query_row("SELECT ?1", [state], |row| row.get(0))
+            .unwrap();
+        assert_eq!(back, state);
+    }
+    assert!(ctx
+        .query_row("SELECT 5", [], |row| row.get::<_, TaskState>(0))
+        .is_err());
+    assert!(ctx
+        .query_row("SELECT 3", [], |row| row.get::<_, BgmState>(0))
+        .is_err());
+}
@@ -1,49 +1,89 @@
-use crate::aria2;
 use crate::bgminfo;
+use crate::config::{config, ScoreConfig};
 use crate::db;
+use crate::downloader::{downloader, DownloadFile, DownloadState, Notification};
+use crate::error::Result;
+use crate::history::{self, History};
 use crate::log;
-use crate::moe;
 use crate::proc;
+use crate::score;
+use crate::source::{self, Release};
+use crate::state::{BgmState, TaskState, Transition};
 use crate::taskinfo;
-use chrono::{prelude::*, Days};
+use crate::title;
+use chrono::{prelude::*, Days, TimeDelta};
 use regex::Regex;
-use tokio::{signal::ctrl_c, sync::mpsc};
-use tracing::{debug, error, info};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::path::Path;
+use tokio::{
+    signal::ctrl_c,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+use tracing::{debug, error, info, warn};
 
-fn generate_tasks() -> Result<(), Box<dyn std::error::Error>> {
+#[cfg(test)]
+mod tests;
+
+/// Delay before retrying after the database failed us in the main loop.
+const RETRY_SECS: u64 = 60;
+/// Interval of looking for subscriptions and retries made outside the daemon.
+const POLL_SECS: u64 = 60;
+
+fn generate_tasks() -> Result<()> {
     let bgms = bgminfo::get_new_bgms()?;
     let mut tasks: Vec<taskinfo::Task> = Vec::new();
     let days = Days::new(7);
     let now = Local::now();
     for mut bgm in bgms {
         let start_date = NaiveDate::parse_from_str(&bgm.start_date, "%Y%m%d");
-        let weekday = Weekday::try_from(bgm.weekday - 1);
-        if weekday.is_err() || start_date.is_err() {
-            bgm.state = 2;
+        let weekday = bgm
+            .weekday
+            .checked_sub(1)
+            .and_then(|wd| Weekday::try_from(wd).ok());
+        let clock = NaiveTime::from_hms_opt(bgm.clock as u32, 0, 0);
+        let (Ok(mut begin), Some(wd), Some(clock)) = (start_date, weekday, clock) else {
+            error!("invalid start_date, weekday or clock of bgm:{}", bgm.id);
+            if bgm
+                .state
+                .transition(BgmState::Invalid, format!("bgm:{}", bgm.id))
+            {
+                bgminfo::update_bgm_state(bgm)?;
+            }
             continue;
-        }
+        };
 
-        let mut begin = start_date.unwrap();
-        let wd = weekday.unwrap();
         let mut idx = 0;
 
         while idx < bgm.episode_count {
+            let Some(episode) = bgm
+                .episode
+                .checked_add(idx)
+                .and_then(|e| bgm.task_episode(e))
+            else {
+                error!("episodes of bgm:{} are out of range", bgm.id);
+                break;
+            };
             tasks.push(taskinfo::Task {
                 id: 0,
                 bgm_id: bgm.id,
-                episode: bgm.episode + idx,
-                regex: format!(".*{}.*{:02}.*", bgm.regex, bgm.episode + idx),
+                episode,
+                regex: taskinfo::episode_regex(&bgm.regex, &bgm.release_episodes(episode)),
                 path: bgm.path.clone(),
                 uri: "".to_string(),
                 gid: "".to_string(),
-                exec_time: Local
-                    .from_local_datetime(&begin.and_hms_opt(bgm.clock as u32, 00, 00).unwrap())
-                    .unwrap()
+                exec_time: begin
+                    .and_time(clock)
                     .format("%Y-%m-%d %H:%M:%S")
                     .to_string(),
                 create_time: now.format("%Y-%m-%d %H:%M:%S").to_string(),
                 finish_time: "".to_string(),
-                state: 0,
+                state: TaskState::Pending,
+                grace_deadline: "".to_string(),
+                title: "".to_string(),
+                files: Vec::new(),
+                replaces: Vec::new(),
+                special: None,
             });
 
             idx += 1;
@@ -57,117 +97,820 @@ fn generate_tasks() -> Result<(), Box<dyn std::error::Error>> {
                 begin.checked_add_days(days).unwrap()
             };
         }
-        bgm.state = 1;
-        bgminfo::update_bgm_state(bgm)?;
+        if bgm
+            .state
+            .transition(BgmState::Scheduled, format!("bgm:{}", bgm.id))
+        {
+            bgminfo::update_bgm_state(bgm)?;
+        }
     }
 
     taskinfo::generate_tasks(&tasks)
 }
 
-async fn update_task_status(task: &mut taskinfo::Task) {
-    let rs = aria2::status(&task.gid).await;
-    match rs {
-        Ok((s, c, t)) => {
-            if s == "complete" || c == t {
-                debug!("task:{} is completed!", task.id);
-                task.state = 1;
+/// Polls the download shared by `tasks`, the tasks a batch release was
+/// handed to or a single one.
+async fn update_task_status(tasks: &mut [&mut taskinfo::Task]) {
+    let Some(gid) = tasks.first().map(|t| t.gid.clone()) else {
+        return;
+    };
+    let ids = task_ids(tasks);
+    match downloader().status(&gid).await {
+        Ok(status) => {
+            // a magnet only fetches the metadata, the real download follows it
+            if let Some(id) = &status.followed_by {
+                debug!("task:{} gid:{} is followed by gid:{}", ids, gid, id);
+                for task in tasks.iter_mut() {
+                    task.gid = id.clone();
+                }
+                select_files(tasks).await;
+                return;
+            }
+            if let DownloadState::Failed(_) | DownloadState::Removed = status.state {
+                warn!("task:{} gid:{} {:?}", ids, gid, status.state);
+                let mut removed = false;
+                for task in tasks.iter_mut() {
+                    if task
+                        .state
+                        .transition(TaskState::Ready, format!("task:{}", task.id))
+                    {
+                        if !removed {
+                            let _ = downloader().remove(&gid).await;
+                            removed = true;
+                        }
+                        // look for a torrent again instead of hammering a dead one
+                        task.uri.clear();
+                        task.gid.clear();
+                    }
+                }
+                return;
+            }
+            if !status.is_finished() {
+                select_files(tasks).await;
+                return;
+            }
+            debug!("task:{} is completed!", ids);
+            let files = match downloader().files(&gid).await {
+                Ok(files) => files.into_iter().filter(|f| f.selected).collect(),
+                // without the files the replaced ones may be the new ones,
+                // poll again rather than delete them
+                Err(e) if tasks.iter().any(|t| !t.replaces.is_empty()) => {
+                    warn!("get files of task:{} error:{:?}, retry later", ids, e);
+                    return;
+                }
+                Err(e) => {
+                    warn!("get files of task:{} error:{:?}", ids, e);
+                    Vec::new()
+                }
+            };
+            let mut finished = false;
+            for task in tasks.iter_mut() {
+                if !task
+                    .state
+                    .transition(TaskState::Done, format!("task:{}", task.id))
+                {
+                    continue;
+                }
+                finished = true;
                 task.finish_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-                let _ = aria2::remove(&task.gid);
+                // a batch holds the episodes of other tasks too, keep to ours
+                let numbers = release_numbers(task);
+                let own: Vec<&DownloadFile> = files
+                    .iter()
+                    .filter(|f| title::file_episode(&f.path).is_some_and(|e| numbers.contains(&e)))
+                    .collect();
+                let own = if own.is_empty() {
+                    files.iter().collect()
+                } else {
+                    own
+                };
+                task.files = own
+                    .into_iter()
+                    .map(|f| Path::new(&task.path).join(&f.path).display().to_string())
+                    .collect();
+                remove_replaced(task);
+            }
+            if finished {
+                if let Err(e) = downloader().remove(&gid).await {
+                    error!("remove task:{} gid:{} error:{:?}", ids, gid, e);
+                }
             }
         }
-        Err(e) => error!("get task:{} status error:{:?}", task.id, e),
+        Err(e) => error!("get task:{} status error:{:?}", ids, e),
     }
 }
-async fn exec_task(task: &mut taskinfo::Task, torrents: &Vec<moe::Torrent>) {
-    if task.uri.len() == 0 && torrents.len() > 0 {
-        let re = Regex::new(&task.regex);
-        if re.is_err() {
-            error!("invalid regex:{} of task:{}", task.regex, task.id);
-            task.state = 4;
+
+/// Ids of `tasks` for logs, e.g. `3,4,5`.
+fn task_ids(tasks: &[&mut taskinfo::Task]) -> String {
+    tasks
+        .iter()
+        .map(|t| t.id.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Narrows a download holding several episodes down to the files of
+/// `tasks`, once the client knows its files. Files of no known episode are
+/// all kept when none matches.
+async fn select_files(tasks: &[&mut taskinfo::Task]) {
+    let Some(first) = tasks.first() else {
+        return;
+    };
+    let parsed = title::parse(&first.title);
+    if tasks.len() < 2 && parsed.batch.is_none() && parsed.episode.is_some() {
+        return;
+    }
+    let files = match downloader().files(&first.gid).await {
+        Ok(files) => files,
+        Err(e) => {
+            debug!("get files of task:{} error:{:?}", task_ids(tasks), e);
             return;
         }
-        let re = re.unwrap();
-        for t in torrents {
-            if re.is_match(&t.title) {
-                info!("task:{}, title:{}, {}", task.id, t.title, t.magnet);
+    };
+    let episodes: Vec<u32> = tasks.iter().flat_map(|t| release_numbers(t)).collect();
+    let wanted: Vec<u32> = files
+        .iter()
+        .filter(|f| title::file_episode(&f.path).is_some_and(|e| episodes.contains(&e)))
+        .map(|f| f.index)
+        .collect();
+    let selected: Vec<u32> = files
+        .iter()
+        .filter(|f| f.selected)
+        .map(|f| f.index)
+        .collect();
+    if wanted.is_empty() || wanted == selected {
+        return;
+    }
+    info!(
+        "task:{} downloads {} of {} files",
+        task_ids(tasks),
+        wanted.len(),
+        files.len()
+    );
+    if let Err(e) = downloader().select(&first.gid, &wanted).await {
+        error!("select files of task:{} error:{:?}", task_ids(tasks), e);
+    }
+}
+
+/// Numbers releases may give the episode of `task`, by the numbering rules
+/// of its bgm.
+fn release_numbers(task: &taskinfo::Task) -> Vec<u32> {
+    match bgminfo::get_bgm(task.bgm_id) {
+        Ok(bgm) => task_numbers(task, bgm.as_ref()),
+        Err(e) => {
+            error!("get bgm:{} error: {}", task.bgm_id, e);
+            task_numbers(task, None)
+        }
+    }
+}
+
+/// Numbers releases may give the episode of `task` by the rules of `bgm`,
+/// none for a special.
+fn task_numbers(task: &taskinfo::Task, bgm: Option<&bgminfo::Bgm>) -> Vec<u32> {
+    match (task.special, bgm) {
+        (Some(_), _) => Vec::new(),
+        (None, Some(bgm)) => bgm.release_episodes(task.episode),
+        (None, None) => vec![task.episode],
+    }
+}
+
+/// Polls every download of `tasks`, once per download shared by several.
+async fn update_downloads(tasks: &mut [taskinfo::Task], gid: Option<&str>) {
+    let mut gids: Vec<String> = tasks
+        .iter()
+        .filter(|t| t.state == TaskState::Downloading && gid.is_none_or(|g| t.gid == g))
+        .map(|t| t.gid.clone())
+        .collect();
+    gids.sort_unstable();
+    gids.dedup();
+    for gid in gids {
+        let mut group: Vec<&mut taskinfo::Task> = tasks
+            .iter_mut()
+            .filter(|t| t.state == TaskState::Downloading && t.gid == gid)
+            .collect();
+        update_task_status(&mut group).await;
+    }
+}
+
+/// Deletes the files of the release a finished task replaced, unless the new
+/// release wrote the same ones.
+fn remove_replaced(task: &mut taskinfo::Task) {
+    for file in std::mem::take(&mut task.replaces) {
+        if task.files.contains(&file) {
+            continue;
+        }
+        match std::fs::remove_file(&file) {
+            Ok(()) => info!("task:{} removed replaced file {}", task.id, file),
+            Err(e) => warn!(
+                "remove replaced file {} of task:{} error:{}",
+                file, task.id, e
+            ),
+        }
+    }
+}
+
+/// Whether a task whose best candidate isn't of a preferred group keeps
+/// waiting for one, starting the grace window on the first call.
+fn waits(task: &mut taskinfo::Task, grace_hours: u32) -> bool {
+    if grace_hours == 0 {
+        return false;
+    }
+    let now = Local::now().naive_local();
+    let deadline = match NaiveDateTime::parse_from_str(&task.grace_deadline, "%Y-%m-%d %H:%M:%S") {
+        Ok(deadline) => deadline,
+        Err(_) => {
+            let deadline = now + TimeDelta::hours(grace_hours as i64);
+            task.grace_deadline = deadline.format("%Y-%m-%d %H:%M:%S").to_string();
+            deadline
+        }
+    };
+    if now < deadline {
+        info!(
+            "task:{} waits for a preferred group until {}",
+            task.id, task.grace_deadline
+        );
+        return true;
+    }
+    false
+}
+
+/// Whether the release may be the one of `task`, whose episode releases
+/// number any of `numbers`. The pattern alone takes 08 for the 08 of 1080p.
+fn covers(release: &Release, task: &taskinfo::Task, numbers: &[u32]) -> bool {
+    let parsed = title::parse(&release.title);
+    match task.special {
+        Some(special) => parsed.special == Some(special),
+        None => numbers.iter().any(|n| parsed.covers(*n)),
+    }
+}
+
+/// Whether `info_hash` went to the downloader before, which it mustn't
+/// again unless a user forces it.
+fn sent_before(info_hash: &str, tasks: &str) -> bool {
+    match history::is_sent(info_hash) {
+        Ok(sent) => {
+            if sent {
+                info!("task:{} skips {}, it was sent before", tasks, info_hash);
+            }
+            sent
+        }
+        Err(e) => {
+            error!(
+                "look up torrent {} of task:{} error: {}",
+                info_hash, tasks, e
+            );
+            true
+        }
+    }
+}
+
+/// Records the release of `task` as sent, in the torrent history and the
+/// events of the task.
+fn record_sent(task: &taskinfo::Task, event: history::Event, info_hash: &str, replaced: String) {
+    if let Err(e) = history::mark_sent(task.bgm_id, info_hash, &task.title, &task.uri) {
+        error!(
+            "mark torrent {} of task:{} sent error: {}",
+            info_hash, task.id, e
+        );
+    }
+    if let Err(e) = history::add_history(&History {
+        task_id: task.id,
+        bgm_id: task.bgm_id,
+        episode: task.episode,
+        event,
+        title: task.title.clone(),
+        uri: task.uri.clone(),
+        replaced,
+        info_hash: info_hash.to_string(),
+        time: String::new(),
+    }) {
+        error!("add history of task:{} error: {}", task.id, e);
+    }
+}
+
+/// Hands the batch release holding the most unmatched tasks of a bgm, two
+/// at least, to all of them at once, downloading only their files.
+async fn exec_batches(tasks: &mut [taskinfo::Task], releases: &Releases) {
+    let profile = &config().score;
+    for (bgm_id, bgm) in &releases.bgms {
+        let Ok(re) = Regex::new(&bgm.regex) else {
+            continue;
+        };
+        // ids of the unmatched episodes with the numbers releases may give
+        // them, batches don't hold specials
+        let wanted: Vec<(u32, Vec<u32>)> = tasks
+            .iter()
+            .filter(|t| {
+                t.bgm_id == *bgm_id
+                    && t.state == TaskState::Ready
+                    && t.uri.is_empty()
+                    && t.special.is_none()
+            })
+            .map(|t| (t.id, bgm.release_episodes(t.episode)))
+            .collect();
+        if wanted.len() < 2 {
+            continue;
+        }
+        let mut best: Option<(&Release, Vec<u32>, score::Score)> = None;
+        for t in releases.of(*bgm_id) {
+            if !re.is_match(&t.title) || !score::acceptable(t, profile) {
+                continue;
+            }
+            let episodes = t.episodes();
+            let held: Vec<u32> = wanted
+                .iter()
+                .filter(|(_, numbers)| numbers.iter().any(|n| episodes.contains(n)))
+                .map(|(id, _)| *id)
+                .collect();
+            if held.len() < 2 {
+                continue;
+            }
+            let score = score::score(t, &title::parse(&t.title), profile);
+            if best
+                .as_ref()
+                .is_none_or(|(_, h, s)| (held.len(), score) > (h.len(), *s))
+            {
+                best = Some((t, held, score));
+            }
+        }
+        let Some((t, held, score)) = best else {
+            continue;
+        };
+
+        let mut batch: Vec<&mut taskinfo::Task> = tasks
+            .iter_mut()
+            .filter(|task| {
+                task.bgm_id == *bgm_id
+                    && task.state == TaskState::Ready
+                    && task.uri.is_empty()
+                    && held.contains(&task.id)
+            })
+            .collect();
+        if score.group == 0 && !profile.groups.is_empty() {
+            // every task starts its grace window, not only the first one
+            let mut waiting = false;
+            for task in batch.iter_mut() {
+                waiting |= waits(task, bgm.grace_hours);
+            }
+            if waiting {
+                continue;
+            }
+        }
+        info!(
+            "task:{}, batch:{}, {}, score:{:?}",
+            task_ids(&batch),
+            t.title,
+            t.magnet,
+            score
+        );
+        if sent_before(&t.hash(), &task_ids(&batch)) {
+            continue;
+        }
+        let numbers: Vec<u32> = wanted
+            .iter()
+            .filter(|(id, _)| held.contains(id))
+            .flat_map(|(_, numbers)| numbers.iter().copied())
+            .collect();
+        let files = t.files_of(&numbers);
+        let gid = match downloader()
+            .add_files(&t.magnet, &batch[0].path, Some(t.download_name()), &files)
+            .await
+        {
+            Ok(gid) => gid,
+            Err(e) => {
+                error!("download batch of task:{} error:{:?}", task_ids(&batch), e);
+                continue;
+            }
+        };
+        for task in batch.iter_mut() {
+            if task
+                .state
+                .transition(TaskState::Downloading, format!("task:{}", task.id))
+            {
                 task.uri = t.magnet.clone();
-                break;
+                task.title = t.title.clone();
+                task.gid = gid.clone();
+                record_sent(task, history::Event::Download, &t.hash(), String::new());
             }
         }
     }
+}
+
+/// Whether the grace window of `task` was started and has run out.
+fn grace_over(task: &taskinfo::Task) -> bool {
+    NaiveDateTime::parse_from_str(&task.grace_deadline, "%Y-%m-%d %H:%M:%S")
+        .is_ok_and(|deadline| deadline <= Local::now().naive_local())
+}
+
+/// `releases` and those of `seen` that aren't among them, the feeds may have
+/// dropped a release since it was seen.
+fn with_seen<'a>(mut releases: Vec<&'a Release>, seen: &'a [Release]) -> Vec<&'a Release> {
+    let hashes: HashSet<String> = releases.iter().map(|r| r.hash()).collect();
+    releases.extend(seen.iter().filter(|r| !hashes.contains(&r.hash())));
+    releases
+}
+
+/// The best release of `releases` for `task` with the number of candidates,
+/// none while the task waits for a preferred group.
+fn choose<'a>(
+    task: &mut taskinfo::Task,
+    releases: &[&'a Release],
+    re: &Regex,
+    numbers: &[u32],
+    grace_hours: u32,
+    profile: &ScoreConfig,
+) -> Option<(&'a Release, score::Score, usize)> {
+    let candidates: Vec<&Release> = releases
+        .iter()
+        .filter(|t| re.is_match(&t.title) && covers(t, task, numbers))
+        .copied()
+        .collect();
+    let (t, score) = score::best(&candidates, profile)?;
+    if score.group == 0 && !profile.groups.is_empty() && waits(task, grace_hours) {
+        return None;
+    }
+    Some((t, score, candidates.len()))
+}
+
+async fn exec_task(task: &mut taskinfo::Task, releases: &[&Release], bgm: Option<&bgminfo::Bgm>) {
+    let numbers = task_numbers(task, bgm);
+    if task.uri.is_empty() && !releases.is_empty() {
+        let Ok(re) = Regex::new(&task.regex) else {
+            error!("invalid regex:{} of task:{}", task.regex, task.id);
+            task.state
+                .transition(TaskState::BadRegex, format!("task:{}", task.id));
+            return;
+        };
+        let grace_hours = bgm.map_or(0, |b| b.grace_hours);
+        let Some((t, score, count)) =
+            choose(task, releases, &re, &numbers, grace_hours, &config().score)
+        else {
+            return;
+        };
+        info!(
+            "task:{}, title:{}, {}, score:{:?} of {} candidates",
+            task.id, t.title, t.magnet, score, count
+        );
+        task.uri = t.magnet.clone();
+        task.title = t.title.clone();
+    }
 
-    if task.uri.len() != 0 {
-        match aria2::download(&task.uri, &task.path, "").await {
+    if !task.uri.is_empty() {
+        let release = releases.iter().find(|t| t.magnet == task.uri);
+        let name = release
+            .map(|t| t.download_name())
+            .or(Some(task.title.as_str()).filter(|t| !t.is_empty()));
+        let files = release.map_or(Vec::new(), |t| t.files_of(&numbers));
+        let info_hash = release.map_or_else(|| history::uri_hash(&task.uri), |t| t.hash());
+        if sent_before(&info_hash, &task.id.to_string()) {
+            // another task took it, look again once the sources are fetched
+            task.uri.clear();
+            task.title.clear();
+            return;
+        }
+        match downloader()
+            .add_files(&task.uri, &task.path, name, &files)
+            .await
+        {
             Ok(gid) => {
-                task.state = 3;
-                task.gid = gid;
+                if task
+                    .state
+                    .transition(TaskState::Downloading, format!("task:{}", task.id))
+                {
+                    task.gid = gid;
+                    record_sent(task, history::Event::Download, &info_hash, String::new());
+                }
             }
             Err(e) => error!("download task:{} error:{:?}", task.id, e),
         }
     }
 }
 
+/// Sends a done task's release off to be replaced when a strictly better one
+/// matches it.
+async fn upgrade_task(task: &mut taskinfo::Task, releases: &[&Release], numbers: &[u32]) {
+    let Ok(re) = Regex::new(&task.regex) else {
+        return;
+    };
+    let profile = &config().score;
+    let upgrades: Vec<&Release> = releases
+        .iter()
+        .filter(|t| {
+            t.magnet != task.uri
+                && re.is_match(&t.title)
+                && covers(t, task, numbers)
+                && score::is_upgrade(&task.title, t, profile)
+        })
+        .copied()
+        .collect();
+    let Some((t, _)) = score::best(&upgrades, profile) else {
+        return;
+    };
+    if sent_before(&t.hash(), &task.id.to_string()) {
+        return;
+    }
+    let files = t.files_of(numbers);
+    let gid = match downloader()
+        .add_files(&t.magnet, &task.path, Some(t.download_name()), &files)
+        .await
+    {
+        Ok(gid) => gid,
+        Err(e) => {
+            error!("download upgrade of task:{} error:{:?}", task.id, e);
+            return;
+        }
+    };
+    if !task
+        .state
+        .transition(TaskState::Downloading, format!("task:{}", task.id))
+    {
+        return;
+    }
+    info!("task:{} replaces {} by {}", task.id, task.title, t.title);
+    task.replaces = std::mem::take(&mut task.files);
+    let replaced = std::mem::replace(&mut task.title, t.title.clone());
+    task.uri = t.magnet.clone();
+    task.gid = gid;
+    record_sent(task, history::Event::Replace, &t.hash(), replaced);
+}
+
+/// Applies a notification of the download client to the tasks it belongs
+/// to.
+async fn handle_notification(tasks: &mut Vec<taskinfo::Task>, n: Notification) {
+    let ids: Vec<u32> = tasks
+        .iter()
+        .filter(|t| t.state == TaskState::Downloading && t.gid == n.id)
+        .map(|t| t.id)
+        .collect();
+    if ids.is_empty() {
+        return;
+    }
+    debug!("task:{:?} got {:?}", ids, n.event);
+    // the status tells a finished magnet from its torrent download
+    update_downloads(tasks, Some(&n.id)).await;
+    for task in tasks.iter().filter(|t| ids.contains(&t.id)) {
+        if let Err(e) = taskinfo::update_task(task) {
+            error!("update task:{} error: {}", task.id, e);
+        }
+    }
+    tasks.retain(|t| t.state != TaskState::Done);
+}
+
+/// Releases of the sources the unmatched tasks look in, by source spec.
+#[derive(Default)]
+struct Releases {
+    by_source: HashMap<String, Vec<Release>>,
+    sources_of: HashMap<u32, Vec<String>>,
+    bgms: HashMap<u32, bgminfo::Bgm>,
+    /// infohashes sent to the downloader before
+    sent: HashSet<String>,
+}
+
+impl Releases {
+    fn all_of(&self, bgm_id: u32) -> impl Iterator<Item = &Release> {
+        self.sources_of
+            .get(&bgm_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|spec| self.by_source.get(spec))
+            .flatten()
+    }
+
+    /// Numbers releases may give the episode of `task`.
+    fn numbers(&self, task: &taskinfo::Task) -> Vec<u32> {
+        task_numbers(task, self.bgms.get(&task.bgm_id))
+    }
+
+    /// Releases of the sources of a bgm that weren't sent before.
+    fn of(&self, bgm_id: u32) -> Vec<&Release> {
+        self.all_of(bgm_id)
+            .filter(|r| !self.sent.contains(&r.hash()))
+            .collect()
+    }
+
+    /// Releases once seen matching a bgm that weren't sent before.
+    fn seen_of(&self, bgm_id: u32) -> Vec<Release> {
+        match history::get_seen(bgm_id) {
+            Ok(seen) => seen
+                .into_iter()
+                .filter(|r| !self.sent.contains(&r.hash()))
+                .collect(),
+            Err(e) => {
+                error!("get seen torrents of bgm:{} error: {}", bgm_id, e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Fetches every source used by `tasks`, each back to the earliest
+/// `exec_time` of the tasks using it. `last` is moved on when any source
+/// answered.
+async fn fetch_releases(tasks: &[&taskinfo::Task], last: &mut NaiveDateTime) -> Releases {
+    let mut releases = Releases::default();
+    let mut since: HashMap<String, NaiveDateTime> = HashMap::new();
+    for task in tasks {
+        let Ok(exec_time) = NaiveDateTime::parse_from_str(&task.exec_time, "%Y-%m-%d %H:%M:%S")
+            .map_err(|e| error!("invalid exec_time of task:{}: {}", task.id, e))
+        else {
+            continue;
+        };
+        if let Entry::Vacant(entry) = releases.sources_of.entry(task.bgm_id) {
+            let sources = match bgminfo::get_bgm(task.bgm_id) {
+                Ok(Some(bgm)) => {
+                    let sources = bgm.sources().to_vec();
+                    releases.bgms.insert(bgm.id, bgm);
+                    sources
+                }
+                Ok(None) => Vec::new(),
+                Err(e) => {
+                    error!("get bgm:{} error: {}", task.bgm_id, e);
+                    continue;
+                }
+            };
+            entry.insert(sources);
+        }
+        for spec in &releases.sources_of[&task.bgm_id] {
+            since
+                .entry(spec.clone())
+                .and_modify(|t| *t = (*t).min(exec_time))
+                .or_insert(exec_time);
+        }
+    }
+
+    for (spec, since) in since {
+        let fetched = match source::parse(&spec) {
+            Ok(source) => source.releases(&since).await,
+            Err(e) => Err(e),
+        };
+        match fetched {
+            Ok(mut result) => {
+                debug!("{} releases from source {}", result.len(), spec);
+                // whatever order the source lists them in, equal ones rank
+                // the same on every fetch
+                result.sort_by_key(|r| r.publish_time);
+                *last = Local::now().naive_local();
+                releases.by_source.insert(spec, result);
+            }
+            Err(e) => error!(
+                "get releases of {} failed, please check your proxy config! {:?}",
+                spec, e
+            ),
+        }
+    }
+
+    for (bgm_id, bgm) in &releases.bgms {
+        let Ok(re) = Regex::new(&bgm.regex) else {
+            continue;
+        };
+        let seen: Vec<&Release> = releases
+            .all_of(*bgm_id)
+            .filter(|r| re.is_match(&r.title))
+            .collect();
+        if let Err(e) = history::add_seen(*bgm_id, &seen) {
+            error!("add seen torrents of bgm:{} error: {}", bgm_id, e);
+        }
+    }
+    match history::sent_hashes() {
+        Ok(sent) => releases.sent = sent,
+        Err(e) => error!("get sent torrents error: {}", e),
+    }
+    releases
+}
+
+/// Takes the episode, pattern and path of the unsent Ready tasks from the
+/// database again after `bgm edit` may have renumbered them, and drops the
+/// ones that are gone with their bgm.
+fn reload_unsent(tasks: &mut Vec<taskinfo::Task>) {
+    let saved: HashMap<u32, taskinfo::Task> =
+        match taskinfo::get_tasks(None, &[TaskState::Pending, TaskState::Ready]) {
+            Ok(saved) => saved.into_iter().map(|t| (t.id, t)).collect(),
+            Err(e) => {
+                error!("reload tasks error: {}", e);
+                return;
+            }
+        };
+    tasks.retain_mut(|task| {
+        if task.state != TaskState::Ready || !task.uri.is_empty() {
+            return true;
+        }
+        let Some(saved) = saved.get(&task.id) else {
+            info!("task:{} is gone", task.id);
+            return false;
+        };
+        task.episode = saved.episode;
+        task.regex = saved.regex.clone();
+        task.path = saved.path.clone();
+        true
+    });
+}
+
+/// Runs the ready tasks, and polls the downloading ones when `reconcile` is
+/// set, as they are otherwise driven by notifications.
 async fn exec_tasks(
     tasks: &mut Vec<taskinfo::Task>,
     last: &mut NaiveDateTime,
-) -> Result<(), Box<dyn std::error::Error>> {
+    reconcile: bool,
+) -> Result<()> {
     let mut new_tasks = taskinfo::get_ready_tasks()?;
+    for task in new_tasks.iter_mut() {
+        task.state
+            .transition(TaskState::Ready, format!("task:{}", task.id));
+    }
     tasks.append(&mut new_tasks);
 
-    let init_state_tasks: Vec<_> = tasks
-        .iter_mut()
-        .filter(|t| t.state == 2 && t.uri == "")
-        .collect();
     let now = Local::now().naive_local();
-    let torrents: Vec<moe::Torrent> =
-        if init_state_tasks.len() > 0 && now.signed_duration_since(*last).num_minutes() > 10 {
-            let earliest_time = &init_state_tasks
-                .iter()
-                .min_by(|t1, t2| t1.exec_time.cmp(&t2.exec_time))
-                .unwrap()
-                .exec_time;
-            let earliest =
-                NaiveDateTime::parse_from_str(earliest_time.as_str(), "%Y-%m-%d %H:%M:%S").unwrap();
+    let mut upgradable = Vec::new();
+    let releases = if now.signed_duration_since(*last).num_minutes() > 10 {
+        upgradable = taskinfo::get_upgradable_tasks()
+            .map_err(|e| error!("get upgradable tasks error: {}", e))
+            .unwrap_or_default();
+        let unmatched: Vec<&taskinfo::Task> = tasks
+            .iter()
+            .filter(|t| t.state == TaskState::Ready && t.uri.is_empty())
+            .chain(&upgradable)
+            .collect();
+        fetch_releases(&unmatched, last).await
+    } else {
+        Releases::default()
+    };
 
-            match moe::get_torrents(&earliest).await {
-                Err(e) => {
-                    error!(
-                        "get torrents failed, please check your proxy config! {:?}",
-                        e
-                    );
-                    Vec::new()
-                }
-                Ok(result) => {
-                    *last = now;
-                    result
-                }
-            }
-        } else {
-            Vec::new()
-        };
+    for mut task in upgradable {
+        let candidates = releases.of(task.bgm_id);
+        let numbers = releases.numbers(&task);
+        upgrade_task(&mut task, &candidates, &numbers).await;
+        if task.state == TaskState::Downloading {
+            tasks.push(task);
+        }
+    }
+
+    if reconcile {
+        update_downloads(tasks, None).await;
+    }
+    exec_batches(tasks, &releases).await;
 
-    for mut task in tasks.iter_mut() {
+    for task in tasks.iter_mut() {
         match task.state {
-            2 => exec_task(&mut task, &torrents).await,
-            1 => task.finish_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-            3 => update_task_status(&mut task).await,
+            TaskState::Ready => {
+                // past the grace window, the releases seen while waiting
+                // count too, even when they left the feeds since
+                let seen = if task.uri.is_empty()
+                    && releases.bgms.contains_key(&task.bgm_id)
+                    && grace_over(task)
+                {
+                    releases.seen_of(task.bgm_id)
+                } else {
+                    Vec::new()
+                };
+                let candidates = with_seen(releases.of(task.bgm_id), &seen);
+                exec_task(task, &candidates, releases.bgms.get(&task.bgm_id)).await
+            }
+            TaskState::Done => {
+                task.finish_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+            }
             _ => (),
         }
 
-        taskinfo::update_task(task)?;
+        if let Err(e) = taskinfo::update_task(task) {
+            error!("update task:{} error: {}", task.id, e);
+        }
     }
 
-    tasks.retain_mut(|t| t.state != 4 && t.state != 1);
+    tasks.retain_mut(|t| t.state != TaskState::BadRegex && t.state != TaskState::Done);
     Ok(())
 }
 
-pub async fn exec() -> Result<(), Box<dyn std::error::Error>> {
+/// Seconds to sleep until the next pending task is due, at least one.
+fn next_exec_secs() -> Result<u64> {
+    let next = taskinfo::get_next_exec_time()?;
+    info!("next active time: {next}");
+    let secs = NaiveDateTime::parse_from_str(next.as_str(), "%Y-%m-%d %H:%M:%S")?
+        .signed_duration_since(Local::now().naive_local())
+        .num_seconds();
+    Ok(secs.max(1) as u64)
+}
+
+pub async fn exec() -> Result<()> {
     let _guard = log::init_log();
-    db::init_db();
-    proc::run_procs();
+    db::init_db()?;
+    proc::supervise().await;
     generate_tasks()?;
     let (tx, mut rx) = mpsc::channel(1);
 
+    // `bgm add`, `bgm retry` and friends write through another connection,
+    // which the update hook doesn't see, so look for their changes as well
+    let poll_tx = tx.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(POLL_SECS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = generate_tasks() {
+                error!("generate tasks error: {}", e);
+            }
+            let _ = poll_tx.try_send(1);
+        }
+    });
+
     tokio::spawn(async move {
         let notify = db::notify();
         let mut sub = notify.subscribe();
@@ -178,8 +921,10 @@ pub async fn exec() -> Result<(), Box<dyn std::error::Error>> {
                         && database == "main"
                         && tbl == "bgm"
                     {
-                        generate_tasks().unwrap();
-                        tx.send(1).await.unwrap();
+                        if let Err(e) = generate_tasks() {
+                            error!("generate tasks error: {}", e);
+                        }
+                        let _ = tx.send(1).await;
                     }
                 }
                 Err(e) => error!("error while recv db notify: {:?}", e),
@@ -187,24 +932,70 @@ pub async fn exec() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
+    let (event_tx, mut events) = mpsc::channel(64);
+    tokio::spawn(async move {
+        downloader().subscribe(event_tx).await;
+    });
+
     tokio::spawn(async move {
+        let reconcile_every = downloader().poll_interval();
         let mut last = NaiveDateTime::UNIX_EPOCH;
+        let mut last_reconcile: Option<Instant> = None;
         let mut secs: u64;
-        let mut tasks: Vec<taskinfo::Task> = taskinfo::get_incomplete_tasks().unwrap();
+        // changes when another connection, e.g. `bgm edit`, commits
+        let mut data_version = None;
+        let mut tasks: Vec<taskinfo::Task> = loop {
+            match taskinfo::get_incomplete_tasks() {
+                Ok(tasks) => break tasks,
+                Err(e) => {
+                    error!(
+                        "get incomplete tasks error: {}, retry in {}s",
+                        e, RETRY_SECS
+                    );
+                    tokio::time::sleep(Duration::from_secs(RETRY_SECS)).await;
+                }
+            }
+        };
 
         loop {
-            exec_tasks(&mut tasks, &mut last).await.unwrap();
-            secs = if tasks.len() > 0 {
+            let reconcile = last_reconcile.is_none_or(|t| t.elapsed() >= reconcile_every);
+            if reconcile {
+                last_reconcile = Some(Instant::now());
+            }
+            match db::data_version() {
+                Ok(version) if data_version != Some(version) => {
+                    if data_version.is_some() {
+                        reload_unsent(&mut tasks);
+                    }
+                    data_version = Some(version);
+                }
+                Ok(_) => (),
+                Err(e) => error!("get data version error: {}", e),
+            }
+            if let Err(e) = exec_tasks(&mut tasks, &mut last, reconcile).await {
+                error!("exec tasks error: {}", e);
+            }
+            secs = if tasks.iter().any(|t| t.state == TaskState::Ready) {
                 1
             } else {
-                let next = taskinfo::get_next_exec_time().unwrap();
-                info!("next active time: {next}");
-                NaiveDateTime::parse_from_str(next.as_str(), "%Y-%m-%d %H:%M:%S")
-                    .unwrap()
-                    .signed_duration_since(Local::now().naive_local())
-                    .num_seconds() as u64
+                let next = match next_exec_secs() {
+                    Ok(secs) => secs,
+                    Err(e) => {
+                        error!("get next exec time error: {}, retry in {}s", e, RETRY_SECS);
+                        RETRY_SECS
+                    }
+                };
+                if tasks.is_empty() {
+                    next
+                } else {
+                    next.min(reconcile_every.as_secs())
+                }
             };
-            let _ = tokio::time::timeout(std::time::Duration::from_secs(secs), rx.recv()).await;
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(secs)) => {}
+                _ = rx.recv() => {}
+                Some(n) = events.recv() => handle_notification(&mut tasks, n).await,
+            }
         }
     });
 
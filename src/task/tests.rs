@@ -0,0 +1,77 @@
+use super::*;
+
+fn release(title: &str, hash: &str) -> Release {
+    Release {
+        title: title.to_string(),
+        magnet: format!("magnet:?xt=urn:btih:{hash}"),
+        info_hash: hash.to_string(),
+        size: 0,
+        publish_time: NaiveDateTime::default(),
+        seeders: None,
+        files: Vec::new(),
+    }
+}
+
+fn task() -> taskinfo::Task {
+    taskinfo::Task {
+        id: 1,
+        bgm_id: 1,
+        episode: 3,
+        regex: taskinfo::episode_regex("Name", &[3]),
+        path: String::new(),
+        uri: String::new(),
+        gid: String::new(),
+        exec_time: String::new(),
+        create_time: String::new(),
+        finish_time: String::new(),
+        state: TaskState::Ready,
+        grace_deadline: String::new(),
+        title: String::new(),
+        files: Vec::new(),
+        replaces: Vec::new(),
+        special: None,
+    }
+}
+
+fn profile() -> ScoreConfig {
+    ScoreConfig {
+        groups: vec!["Preferred".to_string()],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn grace_falls_back_to_seen() {
+    let (mut task, profile) = (task(), profile());
+    let re = Regex::new(&task.regex).unwrap();
+    let other = release("[Other] Name - 03 [1080p]", "aa");
+    let later = release("[Other] Else - 01 [1080p]", "bb");
+
+    // the first candidate isn't of a preferred group, the grace window starts
+    assert!(choose(&mut task, &[&other], &re, &[3], 6, &profile).is_none());
+    assert!(!task.grace_deadline.is_empty());
+    assert!(!grace_over(&task));
+
+    // it ran out after the candidate left the feed
+    task.grace_deadline = "2000-01-01 00:00:00".to_string();
+    assert!(grace_over(&task));
+    assert!(choose(&mut task, &[&later], &re, &[3], 6, &profile).is_none());
+
+    let seen = [other.clone()];
+    let candidates = with_seen(vec![&later], &seen);
+    let (chosen, _, count) = choose(&mut task, &candidates, &re, &[3], 6, &profile).unwrap();
+    assert_eq!(chosen.title, other.title);
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn seen_not_twice() {
+    let current = release("[Other] Name - 03 [1080p]", "aa");
+    let seen = [
+        release("[Other] Name - 03 [1080p]", "aa"),
+        release("[Another] Name - 03 [720p]", "cc"),
+    ];
+    let candidates = with_seen(vec![&current], &seen);
+    let hashes: Vec<String> = candidates.iter().map(|r| r.hash()).collect();
+    assert_eq!(hashes, ["aa", "cc"]);
+}
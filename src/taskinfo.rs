@@ -1,10 +1,18 @@
-use crate::db::db;
+use crate::bgminfo::Bgm;
+use crate::db::{collect_rows, db};
+use crate::error::{Error, Result};
+use crate::state::{TaskState, Transition};
+use crate::title::Special;
+use rusqlite::{types::Type, OptionalExtension, Row};
+
+#[cfg(test)]
+mod tests;
 
 #[derive(Debug)]
 pub struct Task {
     pub id: u32,
     pub bgm_id: u32,
-    pub episode: u8,
+    pub episode: u32,
     pub regex: String,
     pub path: String,
     pub uri: String,
@@ -12,14 +20,57 @@ pub struct Task {
     pub exec_time: String,
     pub create_time: String,
     pub finish_time: String,
-    pub state: u8,
+    pub state: TaskState,
+    /// until when a ready task waits for a preferred group, empty before it
+    /// has seen a candidate
+    pub grace_deadline: String,
+    /// title of the release sent for the task
+    pub title: String,
+    /// files written by the finished download
+    pub files: Vec<String>,
+    /// files of the release being replaced by the running download
+    pub replaces: Vec<String>,
+    /// set for a special, `episode` is then the one it follows
+    pub special: Option<Special>,
+}
+
+fn files_to_sql(files: &[String]) -> String {
+    if files.is_empty() {
+        String::new()
+    } else {
+        serde_json::to_string(files).unwrap_or_default()
+    }
+}
+
+fn special_to_sql(special: Option<Special>) -> String {
+    special.map(|s| s.to_string()).unwrap_or_default()
 }
 
-pub fn generate_tasks(tasks: &Vec<Task>) -> Result<(), Box<dyn std::error::Error>> {
-    let ctx = db().lock()?;
+fn special_from_row(row: &Row, idx: usize) -> rusqlite::Result<Option<Special>> {
+    let special: String = row.get(idx).unwrap_or_default();
+    if special.is_empty() {
+        return Ok(None);
+    }
+    special
+        .parse()
+        .map(Some)
+        .map_err(|e: Error| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+fn files_from_row(row: &Row, idx: usize) -> rusqlite::Result<Vec<String>> {
+    let files: String = row.get(idx).unwrap_or_default();
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(&files)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+pub fn generate_tasks(tasks: &Vec<Task>) -> Result<()> {
+    let ctx = db();
     let mut stmt = ctx.prepare(
-        "INSERT INTO task(bgm_id, episode, regex, path, exec_time, create_time) 
-            VALUES(?1, ?2, ?3, ?4, ?5, ?6)",
+        "INSERT INTO task(bgm_id, episode, regex, path, exec_time, create_time, special)
+            VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)",
     )?;
 
     for task in tasks {
@@ -29,18 +80,19 @@ pub fn generate_tasks(tasks: &Vec<Task>) -> Result<(), Box<dyn std::error::Error
             task.regex,
             task.path,
             task.exec_time,
-            task.create_time
+            task.create_time,
+            special_to_sql(task.special)
         ])?;
     }
     Ok(())
 }
 
-pub fn get_ready_tasks() -> Result<Vec<Task>, Box<dyn std::error::Error>> {
-    let ctx = db().lock()?;
+pub fn get_ready_tasks() -> Result<Vec<Task>> {
+    let ctx = db();
     let mut stmt = ctx
-        .prepare("SELECT id, bgm_id, episode, regex, path, exec_time FROM task WHERE state = 0 and exec_time <= datetime(CURRENT_TIMESTAMP, 'localtime')")
+        .prepare("SELECT id, bgm_id, episode, regex, path, exec_time, special FROM task WHERE state = ?1 and exec_time <= datetime(CURRENT_TIMESTAMP, 'localtime')")
         ?;
-    let tasks = stmt.query_map([], |row| {
+    let tasks = stmt.query_map([TaskState::Pending], |row| {
         Ok(Task {
             id: row.get(0)?,
             bgm_id: row.get(1)?,
@@ -52,22 +104,22 @@ pub fn get_ready_tasks() -> Result<Vec<Task>, Box<dyn std::error::Error>> {
             exec_time: row.get(5)?,
             create_time: "".to_string(),
             finish_time: "".to_string(),
-            state: 2,
+            state: TaskState::Pending,
+            grace_deadline: "".to_string(),
+            title: "".to_string(),
+            files: Vec::new(),
+            replaces: Vec::new(),
+            special: special_from_row(row, 6)?,
         })
     })?;
-    let mut result: Vec<Task> = Vec::new();
-
-    for task in tasks {
-        result.push(task?);
-    }
-
-    Ok(result)
+    Ok(collect_rows(tasks, "task"))
 }
 
-pub fn update_task(task: &Task) -> Result<(), Box<dyn std::error::Error>> {
-    let ctx = db().lock()?;
+pub fn update_task(task: &Task) -> Result<()> {
+    let ctx = db();
     let mut stmt = ctx.prepare(
-        "UPDATE task SET state = ?1, uri = ?2, gid = ?3, finish_time = ?4 WHERE id = ?5 and (state <> ?1 or uri <> ?2 or gid <> ?3 or finish_time <> ?4)",
+        "UPDATE task SET state = ?1, uri = ?2, gid = ?3, finish_time = ?4, grace_deadline = ?6, title = ?7, files = ?8, replaces = ?9
+            WHERE id = ?5 and (state <> ?1 or uri <> ?2 or gid <> ?3 or finish_time <> ?4 or grace_deadline <> ?6 or title <> ?7 or files <> ?8 or replaces <> ?9)",
     )?;
 
     stmt.execute(rusqlite::params![
@@ -75,17 +127,21 @@ pub fn update_task(task: &Task) -> Result<(), Box<dyn std::error::Error>> {
         task.uri,
         task.gid,
         task.finish_time,
-        task.id
+        task.id,
+        task.grace_deadline,
+        task.title,
+        files_to_sql(&task.files),
+        files_to_sql(&task.replaces)
     ])?;
     Ok(())
 }
 
-pub fn get_incomplete_tasks() -> Result<Vec<Task>, Box<dyn std::error::Error>> {
-    let ctx = db().lock()?;
+pub fn get_incomplete_tasks() -> Result<Vec<Task>> {
+    let ctx = db();
     let mut stmt = ctx
-        .prepare("SELECT id, bgm_id, episode, regex, path, uri, gid, exec_time, state FROM task WHERE (state = 2 or state = 3) and datetime(CURRENT_TIMESTAMP, 'localtime')")
+        .prepare("SELECT id, bgm_id, episode, regex, path, uri, gid, exec_time, state, grace_deadline, title, files, replaces, special FROM task WHERE (state = ?1 or state = ?2) and datetime(CURRENT_TIMESTAMP, 'localtime')")
         ?;
-    let tasks = stmt.query_map([], |row| {
+    let tasks = stmt.query_map([TaskState::Ready, TaskState::Downloading], |row| {
         Ok(Task {
             id: row.get(0)?,
             bgm_id: row.get(1)?,
@@ -97,24 +153,145 @@ pub fn get_incomplete_tasks() -> Result<Vec<Task>, Box<dyn std::error::Error>> {
             exec_time: row.get(7)?,
             create_time: "".to_string(),
             finish_time: "".to_string(),
-            state: row.get(8).unwrap_or_default(),
+            state: row.get(8)?,
+            grace_deadline: row.get(9).unwrap_or_default(),
+            title: row.get(10).unwrap_or_default(),
+            files: files_from_row(row, 11)?,
+            replaces: files_from_row(row, 12)?,
+            special: special_from_row(row, 13)?,
         })
     })?;
-    let mut result: Vec<Task> = Vec::new();
-    for task in tasks {
-        result.push(task?);
-    }
-    Ok(result)
+    Ok(collect_rows(tasks, "task"))
 }
 
-pub fn get_next_exec_time() -> Result<String, Box<dyn std::error::Error>> {
-    let ctx = db().lock()?;
+pub fn get_next_exec_time() -> Result<String> {
+    let ctx = db();
     let mut stmt = ctx
-        .prepare("SELECT IFNULL(MIN(exec_time), DATETIME(DATE('now','localtime'), '+1 day')) FROM task WHERE state = 0  AND exec_time BETWEEN DATETIME(DATE('now','localtime')) and DATETIME(DATE('now','localtime') || ' 23:59:59')")
+        .prepare("SELECT IFNULL(MIN(exec_time), DATETIME(DATE('now','localtime'), '+1 day')) FROM task WHERE state = ?1  AND exec_time BETWEEN DATETIME(DATE('now','localtime')) and DATETIME(DATE('now','localtime') || ' 23:59:59')")
         ?;
-    if let Some(row) = stmt.query(())?.next()? {
+    if let Some(row) = stmt.query([TaskState::Pending])?.next()? {
         let time: String = row.get(0)?;
         return Ok(time);
     }
-    Err("select ifnull return no data".into())
+    Err(Error::parse("select ifnull return no data"))
+}
+
+const TASK_COLUMNS: &str =
+    "id, bgm_id, episode, regex, path, uri, gid, exec_time, create_time, finish_time, state, grace_deadline, title, files, replaces, special";
+
+fn task_from_row(row: &Row) -> rusqlite::Result<Task> {
+    Ok(Task {
+        id: row.get(0)?,
+        bgm_id: row.get(1)?,
+        episode: row.get(2)?,
+        regex: row.get(3)?,
+        path: row.get(4)?,
+        uri: row.get(5).unwrap_or_default(),
+        gid: row.get(6).unwrap_or_default(),
+        exec_time: row.get(7)?,
+        create_time: row.get(8).unwrap_or_default(),
+        finish_time: row.get(9).unwrap_or_default(),
+        state: row.get(10)?,
+        grace_deadline: row.get(11).unwrap_or_default(),
+        title: row.get(12).unwrap_or_default(),
+        files: files_from_row(row, 13)?,
+        replaces: files_from_row(row, 14)?,
+        special: special_from_row(row, 15)?,
+    })
+}
+
+/// The title pattern a task is matched with, `numbers` being those releases
+/// may give its episode. Numbers match whatever their width, `7`, `07` or
+/// `007`, but not within other numbers like the 08 of 1080p or the 12 of
+/// 12.5.
+pub fn episode_regex(regex: &str, numbers: &[u32]) -> String {
+    let numbers: Vec<String> = numbers.iter().map(u32::to_string).collect();
+    format!(
+        r".*{}(?:.*\D)?0*(?:{})(?:[^\d.]|\.\D|$).*",
+        regex,
+        numbers.join("|")
+    )
+}
+
+/// The title pattern of a special, the title parser tells which one a
+/// release is.
+pub fn special_regex(regex: &str) -> String {
+    format!(".*{}.*", regex)
+}
+
+/// Tasks of `bgm_id` (or of every bgm), restricted to `states` unless empty.
+pub fn get_tasks(bgm_id: Option<u32>, states: &[TaskState]) -> Result<Vec<Task>> {
+    let mut sql = format!("SELECT {TASK_COLUMNS} FROM task WHERE (?1 IS NULL OR bgm_id = ?1)");
+    if !states.is_empty() {
+        let states: Vec<_> = states.iter().map(|s| (*s as u8).to_string()).collect();
+        sql += &format!(" AND state IN ({})", states.join(", "));
+    }
+    sql += " ORDER BY bgm_id, episode";
+
+    let ctx = db();
+    let mut stmt = ctx.prepare(&sql)?;
+    let tasks = stmt.query_map([bgm_id], task_from_row)?;
+    Ok(collect_rows(tasks, "task"))
+}
+
+/// Done tasks whose bgm still looks for a better release, within
+/// `upgrade_hours` of their `exec_time`.
+pub fn get_upgradable_tasks() -> Result<Vec<Task>> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(&format!(
+        "SELECT {TASK_COLUMNS} FROM task WHERE state = ?1 AND title <> '' AND EXISTS (
+            SELECT 1 FROM bgm WHERE bgm.id = task.bgm_id AND bgm.upgrade_hours > 0
+                AND datetime(task.exec_time, '+' || bgm.upgrade_hours || ' hours') > datetime(CURRENT_TIMESTAMP, 'localtime'))"
+    ))?;
+    let tasks = stmt.query_map([TaskState::Done], task_from_row)?;
+    Ok(collect_rows(tasks, "task"))
+}
+
+pub fn get_task(id: u32) -> Result<Option<Task>> {
+    let ctx = db();
+    let mut stmt = ctx.prepare(&format!("SELECT {TASK_COLUMNS} FROM task WHERE id = ?"))?;
+    Ok(stmt.query_row([id], task_from_row).optional()?)
+}
+
+/// Renumbers and rematches the tasks of a bgm that weren't sent yet after
+/// its pattern, path or numbering rules changed from `old` to `bgm`, giving
+/// the BadRegex ones another go.
+pub fn update_unsent_tasks(old: &Bgm, bgm: &Bgm, path: &str) -> Result<()> {
+    let tasks: Vec<_> = get_tasks(
+        Some(bgm.id),
+        &[TaskState::Pending, TaskState::Ready, TaskState::BadRegex],
+    )?
+    .into_iter()
+    .filter(|t| t.uri.is_empty())
+    .collect();
+
+    let mut ctx = db();
+    let tx = ctx.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "UPDATE task SET episode = ?1, regex = ?2, path = ?3, state = ?4 WHERE id = ?5",
+        )?;
+        for mut task in tasks {
+            // the new pattern deserves another go
+            if task.state == TaskState::BadRegex {
+                task.state
+                    .transition(TaskState::Pending, format!("task:{}", task.id));
+            }
+            let (episode, regex) = if task.special.is_some() {
+                (task.episode, special_regex(&bgm.regex))
+            } else {
+                let episode = old
+                    .season_episode(task.episode)
+                    .and_then(|e| bgm.task_episode(e))
+                    .unwrap_or(task.episode);
+                (
+                    episode,
+                    episode_regex(&bgm.regex, &bgm.release_episodes(episode)),
+                )
+            };
+            stmt.execute(rusqlite::params![episode, regex, path, task.state, task.id])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
 }
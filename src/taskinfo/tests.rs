@@ -0,0 +1,36 @@
+use super::*;
+use regex::Regex;
+
+fn matches(numbers: &[u32], title: &str) -> bool {
+    Regex::new(&episode_regex("Name", numbers))
+        .unwrap()
+        .is_match(title)
+}
+
+#[test]
+fn any_width() {
+    assert!(matches(&[7], "[Group] Name - 7 [1080p]"));
+    assert!(matches(&[7], "[Group] Name - 07 [1080p]"));
+    assert!(matches(&[7], "[Group] Name - 007 [1080p]"));
+    assert!(matches(&[7], "[Group][Name][07][1080p]"));
+    assert!(matches(&[7], "[Group] Name - 07v2"));
+    assert!(matches(&[1085], "[Group] One Name - 1085 [1080p]"));
+    assert!(matches(&[1085], "[Group] One Name - 01085 [1080p]"));
+}
+
+#[test]
+fn within_other_numbers() {
+    assert!(!matches(&[7], "[Group] Name - 17 [1080p]"));
+    assert!(!matches(&[7], "[Group] Name - 70 [1080p]"));
+    assert!(!matches(&[8], "[Group] Name - 12 [1080p]"));
+    assert!(!matches(&[7], "[Group] Name - 07.5 [1080p]"));
+    assert!(!matches(&[85], "[Group] One Name - 1085 [1080p]"));
+    assert!(!matches(&[7], "[Group] Other - 07 [1080p]"));
+}
+
+#[test]
+fn any_numbering() {
+    assert!(matches(&[3, 15], "[Group] Name - 03 [1080p]"));
+    assert!(matches(&[3, 15], "[Group] Name - 15 [1080p]"));
+    assert!(!matches(&[3, 15], "[Group] Name - 13 [1080p]"));
+}
@@ -0,0 +1,661 @@
+use crate::error::{Error, Result};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    H264,
+    H265,
+    Av1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// WEB-DL and the rips of streaming sites like Baha or CR
+    Web,
+    WebRip,
+    BluRay,
+    Tv,
+    Dvd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    /// simplified chinese
+    Chs,
+    /// traditional chinese
+    Cht,
+    Jpn,
+    Eng,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Mkv,
+    Mp4,
+    Avi,
+}
+
+/// An episode outside the numbered ones, written `sp2`, `ova1`, `recap` or
+/// `12.5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Special {
+    /// SP, 特别篇 or 番外
+    Sp(u32),
+    /// OVA or OAD
+    Ova(u32),
+    /// 总集篇
+    Recap,
+    /// `12.5`, between two numbered episodes
+    Fraction(u32, u8),
+}
+
+impl Special {
+    /// The numbered episode the special follows, 0 when it doesn't tell.
+    pub fn episode(self) -> u32 {
+        match self {
+            Special::Fraction(episode, _) => episode,
+            _ => 0,
+        }
+    }
+}
+
+impl Display for Special {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Special::Sp(n) => write!(f, "sp{n}"),
+            Special::Ova(n) => write!(f, "ova{n}"),
+            Special::Recap => f.write_str("recap"),
+            Special::Fraction(episode, tenths) => write!(f, "{episode}.{tenths}"),
+        }
+    }
+}
+
+impl FromStr for Special {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().to_ascii_lowercase();
+        let number = |n: &str| {
+            if n.is_empty() {
+                Some(1)
+            } else {
+                n.parse().ok()
+            }
+        };
+        let special = if s == "recap" {
+            Some(Special::Recap)
+        } else if let Some(n) = s.strip_prefix("sp") {
+            number(n).map(Special::Sp)
+        } else if let Some(n) = s.strip_prefix("ova").or_else(|| s.strip_prefix("oad")) {
+            number(n).map(Special::Ova)
+        } else {
+            s.split_once('.').and_then(|(episode, tenths)| {
+                let tenths = tenths.parse().ok().filter(|t| (1..10).contains(t))?;
+                Some(Special::Fraction(episode.parse().ok()?, tenths))
+            })
+        };
+        special.ok_or_else(|| {
+            Error::parse(format!(
+                "special `{s}` is none of sp[N], ova[N], recap or <episode>.<tenths>"
+            ))
+        })
+    }
+}
+
+/// What a fansub release title tells about the release, fields the title
+/// doesn't mention are left empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedRelease {
+    pub group: Option<String>,
+    pub name: Option<String>,
+    /// unset for batches
+    pub episode: Option<u32>,
+    /// 2 for `v2`, unset for the first version
+    pub version: Option<u8>,
+    /// vertical lines, 1080 for 1080p or 1920x1080
+    pub resolution: Option<u16>,
+    pub codec: Option<Codec>,
+    pub source: Option<Source>,
+    pub subtitles: Vec<Language>,
+    /// first and last episode of a batch
+    pub batch: Option<(u32, u32)>,
+    pub container: Option<Container>,
+    /// set instead of `episode` for specials
+    pub special: Option<Special>,
+}
+
+impl ParsedRelease {
+    /// Whether the release may hold `episode`, releases of unknown episodes
+    /// may hold any. Specials hold none.
+    pub fn covers(&self, episode: u32) -> bool {
+        if self.special.is_some() {
+            return false;
+        }
+        match (self.episode, self.batch) {
+            (Some(e), _) => e == episode,
+            (None, Some((first, last))) => (first..=last).contains(&episode),
+            (None, None) => true,
+        }
+    }
+}
+
+macro_rules! re {
+    ($name:ident, $pattern:expr) => {
+        static $name: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new($pattern).expect("valid regex"));
+    };
+}
+
+re!(
+    RESOLUTION,
+    r"(?i)\b(?:\d{3,4}\s*[x×]\s*(\d{3,4})\b|(360|480|540|576|720|810|900|1080|1440|2160|4320)[pi]|(4K)\b)"
+);
+re!(
+    CODEC,
+    r"(?i)\b(?:(x264|h\.?264|avc)|(x265|h\.?265|hevc)|(av1))"
+);
+re!(
+    SOURCE,
+    r"(?i)\b(?:(web-?rip)|(web-?dl|web|baha|cr|b-global|bilibili|abema|netflix|nf|amzn|iqiyi|viutv|at-x)|(bd-?rip|bd|blu-?ray|bdmv)|(hdtv|tvrip|tv)|(dvd-?rip|dvd))\b"
+);
+re!(CONTAINER, r"(?i)(?:\.|\b)(mkv|mp4|avi)\b");
+// audio, bit depth and other technical bits, only good for telling a tag
+// from a name
+re!(
+    EXTRAS,
+    r"(?i)\b(?:\d+-?bit|hi10p?|ma10p|aac|flac|opus|ac3|e?ac-?3|dts|ddp?[\d.]*|hdr|sdr|fin|end|v\d|raw|\d+(?:\.\d+)?\s*[gm]i?b)\b|新番|月番|番剧|合集|全集|完结|招募|内封|内嵌|外挂"
+);
+re!(
+    YEAR_DATE,
+    r"^(?:19|20)\d{2}(?:[.\-/年]\d{1,2}(?:[.\-/月]\d{1,2}日?)?)?$"
+);
+
+re!(CN_BATCH, r"第\s*(\d{1,4})\s*[-~]\s*(\d{1,4})\s*[话話集回]");
+re!(CN_ALL, r"全\s*(\d{1,4})\s*[话話集回]");
+re!(
+    BRACKET_BATCH,
+    r"(?i)^(\d{1,4})\s*[-~]\s*(\d{1,4})(?:[\s+]*(?:v\d|end|fin|完结?|合集|全集|tv|\+?\s*(?:sp|ova|oad)s?))*$"
+);
+re!(PLAIN_BATCH, r"(?:^|\s)(\d{1,4})\s*[-~]\s*(\d{1,4})(?:\s|$)");
+re!(
+    CN_EPISODE,
+    r"(?i)第\s*(\d{1,4}|[零〇一二两三四五六七八九十百]+)\s*[话話集回](?:\s*v(\d))?"
+);
+re!(
+    EP_EPISODE,
+    r"(?i)(?:\bS\d{1,2}E|\bE|\bEP\.?\s?|\bEpisode\s?)(\d{1,4})(?:v(\d))?\b"
+);
+re!(DASH_EPISODE, r"(?i)\s-\s(\d{1,4})(?:v(\d))?(?:\s|$|[\[(.])");
+re!(
+    BRACKET_EPISODE,
+    r"(?i)^(\d{1,4})(?:v(\d))?(?:\s*(?:end|fin|完))?$"
+);
+re!(
+    FRACTION_EPISODE,
+    r"(?i)(?:\s-\s|\[|第\s*|\bEP?\.?\s?)(\d{1,4})\.([1-9])(?:v\d)?(?:[\]\s话話集回]|$)"
+);
+re!(
+    SP_EPISODE,
+    r"(?i)(?:\b(SP|OVA|OAD)|(特[别別]篇|番外篇?))\s?(\d{1,2})?(?:v\d)?(?:\b|$)"
+);
+re!(RECAP, r"(?i)总集篇|總集篇|\brecap\b");
+re!(WORD, r"\S+");
+re!(LONE_EPISODE, r"(?i)^(\d{1,4})(?:v(\d))?$");
+re!(VERSION, r"(?i)\bv(\d)\b");
+re!(
+    LONE_NUMBER_AFTER,
+    r"(?i)(?:season|part|no\.|vol\.|cour|s)\s*$"
+);
+
+/// Full-width forms to ASCII and the various brackets to `[]`.
+fn normalize(title: &str) -> String {
+    title
+        .chars()
+        .map(|c| match c {
+            '\u{3000}' => ' ',
+            '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap_or(c),
+            '【' | '〔' | '〖' => '[',
+            '】' | '〕' | '〗' => ']',
+            _ => c,
+        })
+        .collect()
+}
+
+/// Text inside or between brackets, with its byte range in the title.
+#[derive(Debug)]
+struct Segment<'a> {
+    start: usize,
+    text: &'a str,
+    bracketed: bool,
+}
+
+fn segments(s: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut plain_start = 0;
+    let mut i = 0;
+    while i < s.len() {
+        let c = s[i..].chars().next().expect("in bounds");
+        let close = match c {
+            '[' => ']',
+            '(' => ')',
+            '★' => '★',
+            _ => {
+                i += c.len_utf8();
+                continue;
+            }
+        };
+        let inner = i + c.len_utf8();
+        let Some(len) = s[inner..].find(close) else {
+            break;
+        };
+        if plain_start < i {
+            segments.push(Segment {
+                start: plain_start,
+                text: &s[plain_start..i],
+                bracketed: false,
+            });
+        }
+        segments.push(Segment {
+            start: inner,
+            text: &s[inner..inner + len],
+            bracketed: true,
+        });
+        i = inner + len + close.len_utf8();
+        plain_start = i;
+    }
+    if plain_start < s.len() {
+        segments.push(Segment {
+            start: plain_start,
+            text: &s[plain_start..],
+            bracketed: false,
+        });
+    }
+    segments
+}
+
+/// `十二`, `二十四` or `一百零五`.
+fn cn_number(s: &str) -> Option<u32> {
+    let (mut total, mut digit) = (0, 0);
+    for c in s.chars() {
+        match "零一二三四五六七八九".chars().position(|d| d == c) {
+            Some(d) => digit = d as u32,
+            None => match c {
+                '〇' => digit = 0,
+                '两' => digit = 2,
+                '十' => {
+                    total += digit.max(1) * 10;
+                    digit = 0;
+                }
+                '百' => {
+                    total += digit.max(1) * 100;
+                    digit = 0;
+                }
+                _ => return None,
+            },
+        }
+    }
+    Some(total + digit)
+}
+
+fn is_year(n: u32, digits: &str) -> bool {
+    digits.len() == 4 && (1900..2100).contains(&n)
+}
+
+fn languages(token: &str) -> Vec<Language> {
+    let mut langs = Vec::new();
+    for word in token
+        .split(|c: char| c.is_whitespace() || "_&+/-,.|()".contains(c))
+        .filter(|w| !w.is_empty())
+    {
+        let found: &[Language] = match word.to_ascii_uppercase().as_str() {
+            "CHS" | "SC" | "GB" | "ZHS" | "CHI" => &[Language::Chs],
+            "CHT" | "TC" | "BIG5" | "ZHT" => &[Language::Cht],
+            "JP" | "JPN" | "JA" | "JAP" => &[Language::Jpn],
+            "ENG" | "EN" => &[Language::Eng],
+            "JPSC" => &[Language::Jpn, Language::Chs],
+            "JPTC" => &[Language::Jpn, Language::Cht],
+            "CHSJP" => &[Language::Chs, Language::Jpn],
+            _ => {
+                langs.extend(cn_languages(word));
+                continue;
+            }
+        };
+        langs.extend_from_slice(found);
+    }
+    langs
+}
+
+/// `简繁日内封`, `中日双语` and the like.
+fn cn_languages(word: &str) -> Vec<Language> {
+    const CHARS: &str = "简簡繁体體中日英文语語双雙字幕内內封嵌外挂掛";
+    if !word.chars().all(|c| CHARS.contains(c)) {
+        return Vec::new();
+    }
+    let mut langs = Vec::new();
+    for c in word.chars() {
+        let lang = match c {
+            '简' | '簡' => Language::Chs,
+            '繁' => Language::Cht,
+            '日' => Language::Jpn,
+            '英' => Language::Eng,
+            '中' if !word.contains(['简', '簡', '繁']) => Language::Chs,
+            _ => continue,
+        };
+        langs.push(lang);
+    }
+    langs
+}
+
+fn is_tag(text: &str) -> bool {
+    let text = &text.trim().replace('_', " ");
+    text.is_empty()
+        || RESOLUTION.is_match(text)
+        || CODEC.is_match(text)
+        || SOURCE.is_match(text)
+        || CONTAINER.is_match(text)
+        || EXTRAS.is_match(text)
+        || YEAR_DATE.is_match(text)
+        || BRACKET_EPISODE.is_match(text)
+        || BRACKET_BATCH.is_match(text)
+        || !languages(text).is_empty()
+}
+
+/// Where the episode or batch was found and what it is.
+struct Episode {
+    start: usize,
+    episode: Option<u32>,
+    batch: Option<(u32, u32)>,
+    version: Option<u8>,
+}
+
+impl Episode {
+    fn single(start: usize, c: &Captures) -> Option<Self> {
+        let digits = c.get(1)?.as_str();
+        let episode = digits.parse().ok().or_else(|| cn_number(digits))?;
+        if is_year(episode, digits) {
+            return None;
+        }
+        Some(Episode {
+            start,
+            episode: Some(episode),
+            batch: None,
+            version: c.get(2).and_then(|v| v.as_str().parse().ok()),
+        })
+    }
+
+    fn batch(start: usize, c: &Captures) -> Option<Self> {
+        let first = c.get(1)?.as_str().parse().ok()?;
+        let last = c.get(2)?.as_str().parse().ok()?;
+        (first < last && last < 1900).then_some(Episode {
+            start,
+            episode: None,
+            batch: Some((first, last)),
+            version: None,
+        })
+    }
+}
+
+fn find_episode(s: &str, segments: &[Segment]) -> Option<Episode> {
+    let in_title = |re: &Regex, batch: bool| {
+        re.captures_iter(s).find_map(|c| {
+            let start = c.get(0)?.start();
+            if batch {
+                Episode::batch(start, &c)
+            } else {
+                Episode::single(start, &c)
+            }
+        })
+    };
+    let in_brackets = |re: &Regex, batch: bool| {
+        segments
+            .iter()
+            .rev()
+            .filter(|seg| seg.bracketed)
+            .find_map(|seg| {
+                let c = re.captures(seg.text.trim())?;
+                if batch {
+                    Episode::batch(seg.start, &c)
+                } else {
+                    Episode::single(seg.start, &c)
+                }
+            })
+    };
+
+    in_title(&CN_BATCH, true)
+        .or_else(|| {
+            CN_ALL.captures(s).and_then(|c| {
+                let last = c.get(1)?.as_str().parse().ok()?;
+                Some(Episode {
+                    start: c.get(0)?.start(),
+                    episode: None,
+                    batch: Some((1, last)),
+                    version: None,
+                })
+            })
+        })
+        .or_else(|| in_brackets(&BRACKET_BATCH, true))
+        .or_else(|| in_title(&CN_EPISODE, false))
+        .or_else(|| in_title(&EP_EPISODE, false))
+        .or_else(|| in_title(&DASH_EPISODE, false))
+        .or_else(|| in_brackets(&BRACKET_EPISODE, false))
+        .or_else(|| {
+            segments
+                .iter()
+                .filter(|seg| !seg.bracketed)
+                .find_map(|seg| {
+                    PLAIN_BATCH
+                        .captures(seg.text)
+                        .and_then(|c| Episode::batch(seg.start + c.get(1)?.start(), &c))
+                })
+        })
+        .or_else(|| {
+            // the last lone number of the plain text, `Name 03`
+            segments
+                .iter()
+                .filter(|seg| !seg.bracketed)
+                .flat_map(|seg| {
+                    WORD.find_iter(seg.text).filter_map(|word| {
+                        let (before, after) = (&seg.text[..word.start()], &seg.text[word.end()..]);
+                        // `Season 2`, or a name starting with a number
+                        if LONE_NUMBER_AFTER.is_match(before)
+                            || (before.trim().is_empty() && !after.trim().is_empty())
+                        {
+                            return None;
+                        }
+                        let c = LONE_EPISODE.captures(word.as_str())?;
+                        Episode::single(seg.start + word.start(), &c)
+                    })
+                })
+                .last()
+        })
+}
+
+/// The special of the title and where it starts, `episode` being the
+/// number found for it and where, if any. A fraction or SP marker only
+/// counts at that place, so `- 03 [5.1]` stays episode 3.
+fn find_special(flat: &str, episode: Option<(usize, u32)>) -> Option<(usize, Special)> {
+    // starts at the episode or right before it, with nothing in between
+    let at_episode = |start: usize, end: usize| {
+        episode.is_none_or(|(at, _)| {
+            start <= at && !flat[end.min(at)..at].chars().any(char::is_alphanumeric)
+        })
+    };
+    let fraction = FRACTION_EPISODE
+        .captures_iter(flat)
+        .find(|c| c.get(0).is_some_and(|m| at_episode(m.start(), m.end())));
+    if let Some(c) = fraction {
+        let special = Special::Fraction(c[1].parse().ok()?, c[2].parse().ok()?);
+        return Some((c.get(0)?.start(), special));
+    }
+    if let Some(m) = RECAP.find(flat) {
+        return Some((m.start(), Special::Recap));
+    }
+    let c = SP_EPISODE
+        .captures_iter(flat)
+        .find(|c| c.get(0).is_some_and(|m| at_episode(m.start(), m.end())))?;
+    let n = c
+        .get(3)
+        .and_then(|n| n.as_str().parse().ok())
+        .or(episode.map(|(_, n)| n))
+        .unwrap_or(1);
+    let special = match c.get(1).map(|m| m.as_str().to_ascii_uppercase()) {
+        Some(kind) if kind != "SP" => Special::Ova(n),
+        _ => Special::Sp(n),
+    };
+    Some((c.get(0)?.start(), special))
+}
+
+fn clean_name(name: &str) -> Option<String> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    let name = name.trim_matches(|c: char| " -_:|/·~".contains(c));
+    (!name.is_empty() && name.parse::<u32>().is_err()).then(|| name.to_string())
+}
+
+/// Episode of a file inside a torrent, told by its name.
+pub fn file_episode(path: &str) -> Option<u32> {
+    parse(path.rsplit(['/', '\\']).next()?).episode
+}
+
+/// Takes apart a fansub release title, e.g.
+/// `[Group] Name - 03v2 [1080p][CHS].mkv` or `[组][名字][第03话][简日双语]`.
+pub fn parse(title: &str) -> ParsedRelease {
+    let s = normalize(title);
+    let segments = segments(&s);
+    // `_` is a word character to regex, `GB_MP4` has no word boundaries
+    let flat = s.replace('_', " ");
+    let mut parsed = ParsedRelease::default();
+
+    // leading brackets, the first that isn't a tag names the group
+    let group = segments
+        .iter()
+        .take_while(|seg| seg.bracketed || seg.text.trim().is_empty())
+        .position(|seg| seg.bracketed && !is_tag(seg.text));
+    parsed.group = group.and_then(|i| clean_name(segments[i].text));
+
+    let episode = find_episode(&s, &segments);
+    let mut name_end = episode.as_ref().map_or(s.len(), |e| e.start);
+    if let Some(e) = &episode {
+        parsed.episode = e.episode;
+        parsed.batch = e.batch;
+        parsed.version = e.version;
+    }
+    // a batch may come with its specials, `01-12+SP`
+    if parsed.batch.is_none() {
+        let found = episode.as_ref().and_then(|e| Some((e.start, e.episode?)));
+        if let Some((start, special)) = find_special(&flat, found) {
+            parsed.special = Some(special);
+            parsed.episode = None;
+            name_end = name_end.min(start);
+        }
+    }
+    parsed.version = parsed.version.or_else(|| {
+        VERSION
+            .captures(&flat)
+            .and_then(|c| c.get(1)?.as_str().parse().ok())
+    });
+
+    // the first non-tag text before the episode, or after it when there is
+    // nothing before
+    let tag_start = |text: &str| {
+        let text = text.replace('_', " ");
+        [&*RESOLUTION, &*CODEC, &*SOURCE, &*CONTAINER]
+            .iter()
+            .filter_map(|re| re.find(&text).map(|m| m.start()))
+            .min()
+            .unwrap_or(text.len())
+    };
+    let candidate = |(i, seg): (usize, &Segment)| {
+        if Some(i) == group {
+            return None;
+        }
+        let mut text = seg.text;
+        if seg.start < name_end && name_end < seg.start + text.len() {
+            text = &text[..name_end - seg.start];
+        }
+        if seg.bracketed {
+            return (!is_tag(text)).then(|| clean_name(text)).flatten();
+        }
+        clean_name(&text[..tag_start(text)])
+    };
+    parsed.name = segments
+        .iter()
+        .enumerate()
+        .filter(|(_, seg)| seg.start < name_end)
+        .find_map(candidate)
+        .or_else(|| {
+            segments
+                .iter()
+                .enumerate()
+                .filter(|(_, seg)| seg.start > name_end)
+                .find_map(candidate)
+        });
+
+    parsed.resolution = RESOLUTION.captures(&flat).and_then(|c| {
+        if c.get(3).is_some() {
+            return Some(2160);
+        }
+        c.get(1).or(c.get(2))?.as_str().parse().ok()
+    });
+    parsed.codec = CODEC.captures(&flat).and_then(|c| {
+        [Codec::H264, Codec::H265, Codec::Av1]
+            .into_iter()
+            .zip(1..)
+            .find_map(|(codec, i)| c.get(i).map(|_| codec))
+    });
+    parsed.source = SOURCE
+        .captures_iter(&flat)
+        .filter(|c| {
+            c.get(0)
+                .is_some_and(|m| m.start() >= name_end || !in_name(&flat, m.start(), &segments))
+        })
+        .find_map(|c| {
+            [
+                Source::WebRip,
+                Source::Web,
+                Source::BluRay,
+                Source::Tv,
+                Source::Dvd,
+            ]
+            .into_iter()
+            .zip(1..)
+            .find_map(|(source, i)| c.get(i).map(|_| source))
+        });
+    parsed.container = CONTAINER.captures_iter(&flat).last().and_then(|c| {
+        match c.get(1)?.as_str().to_ascii_lowercase().as_str() {
+            "mkv" => Some(Container::Mkv),
+            "mp4" => Some(Container::Mp4),
+            "avi" => Some(Container::Avi),
+            _ => None,
+        }
+    });
+
+    for seg in segments.iter().filter(|seg| seg.bracketed) {
+        for lang in languages(seg.text) {
+            if !parsed.subtitles.contains(&lang) {
+                parsed.subtitles.push(lang);
+            }
+        }
+    }
+    parsed
+}
+
+/// Whether `pos` falls in plain text before any tag, where words like `TV`
+/// belong to the name.
+fn in_name(s: &str, pos: usize, segments: &[Segment]) -> bool {
+    segments.iter().any(|seg| {
+        !seg.bracketed
+            && seg.start <= pos
+            && pos < seg.start + seg.text.len()
+            && !RESOLUTION.is_match(&s[seg.start..pos])
+            && !CODEC.is_match(&s[seg.start..pos])
+    })
+}
@@ -0,0 +1,886 @@
+use super::*;
+use Codec::*;
+use Container::*;
+use Language::*;
+
+fn s(s: &str) -> Option<String> {
+    Some(s.to_string())
+}
+
+#[test]
+fn corpus() {
+    let cases = [
+        (
+            "[SubsPlease] Sousou no Frieren - 03 (1080p) [A1B2C3D4].mkv",
+            ParsedRelease {
+                group: s("SubsPlease"),
+                name: s("Sousou no Frieren"),
+                episode: Some(3),
+                resolution: Some(1080),
+                container: Some(Mkv),
+                ..Default::default()
+            },
+        ),
+        (
+            "[Erai-raws] Kusuriya no Hitorigoto - 12v2 [1080p][Multiple Subtitle][ENG][POR-BR]",
+            ParsedRelease {
+                group: s("Erai-raws"),
+                name: s("Kusuriya no Hitorigoto"),
+                episode: Some(12),
+                version: Some(2),
+                resolution: Some(1080),
+                subtitles: vec![Eng],
+                ..Default::default()
+            },
+        ),
+        (
+            "【喵萌奶茶屋】★10月新番★[葬送的芙莉莲 / Sousou no Frieren][03][1080p][简日双语][招募翻译]",
+            ParsedRelease {
+                group: s("喵萌奶茶屋"),
+                name: s("葬送的芙莉莲 / Sousou no Frieren"),
+                episode: Some(3),
+                resolution: Some(1080),
+                subtitles: vec![Chs, Jpn],
+                ..Default::default()
+            },
+        ),
+        (
+            "[Lilith-Raws] 间谍过家家 / Spy x Family - 25 [Baha][WEB-DL][1080p][AVC AAC][CHT][MP4]",
+            ParsedRelease {
+                group: s("Lilith-Raws"),
+                name: s("间谍过家家 / Spy x Family"),
+                episode: Some(25),
+                resolution: Some(1080),
+                codec: Some(H264),
+                source: Some(Source::Web),
+                subtitles: vec![Cht],
+                container: Some(Mp4),
+                ..Default::default()
+            },
+        ),
+        (
+            "[桜都字幕组] 葬送的芙莉莲 / Sousou no Frieren [04][1080p][简繁内封]",
+            ParsedRelease {
+                group: s("桜都字幕组"),
+                name: s("葬送的芙莉莲 / Sousou no Frieren"),
+                episode: Some(4),
+                resolution: Some(1080),
+                subtitles: vec![Chs, Cht],
+                ..Default::default()
+            },
+        ),
+        (
+            "[北宇治字幕组] 药屋少女的呢喃 / Kusuriya no Hitorigoto [05][WebRip][HEVC_AAC][简日内嵌]",
+            ParsedRelease {
+                group: s("北宇治字幕组"),
+                name: s("药屋少女的呢喃 / Kusuriya no Hitorigoto"),
+                episode: Some(5),
+                codec: Some(H265),
+                source: Some(Source::WebRip),
+                subtitles: vec![Chs, Jpn],
+                ..Default::default()
+            },
+        ),
+        (
+            "[ANi] 葬送的芙莉蓮 - 06 [1080P][Baha][WEB-DL][AAC AVC][CHT].mp4",
+            ParsedRelease {
+                group: s("ANi"),
+                name: s("葬送的芙莉蓮"),
+                episode: Some(6),
+                resolution: Some(1080),
+                codec: Some(H264),
+                source: Some(Source::Web),
+                subtitles: vec![Cht],
+                container: Some(Mp4),
+                ..Default::default()
+            },
+        ),
+        (
+            "[LoliHouse] 迷宫饭 / Dungeon Meshi - 07 [WebRip 1080p HEVC-10bit AAC][简繁内封字幕]",
+            ParsedRelease {
+                group: s("LoliHouse"),
+                name: s("迷宫饭 / Dungeon Meshi"),
+                episode: Some(7),
+                resolution: Some(1080),
+                codec: Some(H265),
+                source: Some(Source::WebRip),
+                subtitles: vec![Chs, Cht],
+                ..Default::default()
+            },
+        ),
+        (
+            "[猎户发布组] 我推的孩子 第二季 / Oshi no Ko S2 [02] [1080p] [简中内嵌] [2024年7月番]",
+            ParsedRelease {
+                group: s("猎户发布组"),
+                name: s("我推的孩子 第二季 / Oshi no Ko S2"),
+                episode: Some(2),
+                resolution: Some(1080),
+                subtitles: vec![Chs],
+                ..Default::default()
+            },
+        ),
+        (
+            "[云光字幕组] 药屋少女的呢喃 第08话 [简体双语][1080p]招募翻译",
+            ParsedRelease {
+                group: s("云光字幕组"),
+                name: s("药屋少女的呢喃"),
+                episode: Some(8),
+                resolution: Some(1080),
+                subtitles: vec![Chs],
+                ..Default::default()
+            },
+        ),
+        (
+            "【幻樱字幕组】【10月新番】【葬送的芙莉莲 Sousou no Frieren】【第１２話】【ＢＩＧ５＿ＭＰ４】【１９２０Ｘ１０８０】",
+            ParsedRelease {
+                group: s("幻樱字幕组"),
+                name: s("葬送的芙莉莲 Sousou no Frieren"),
+                episode: Some(12),
+                resolution: Some(1080),
+                subtitles: vec![Cht],
+                container: Some(Mp4),
+                ..Default::default()
+            },
+        ),
+        (
+            "[Nekomoe kissaten][Sousou no Frieren][13][1080p][JPSC]",
+            ParsedRelease {
+                group: s("Nekomoe kissaten"),
+                name: s("Sousou no Frieren"),
+                episode: Some(13),
+                resolution: Some(1080),
+                subtitles: vec![Jpn, Chs],
+                ..Default::default()
+            },
+        ),
+        (
+            "[Nekomoe kissaten&LoliHouse] Sousou no Frieren - 14 [WebRip 1080p HEVC-10bit AAC ASSx2].mkv",
+            ParsedRelease {
+                group: s("Nekomoe kissaten&LoliHouse"),
+                name: s("Sousou no Frieren"),
+                episode: Some(14),
+                resolution: Some(1080),
+                codec: Some(H265),
+                source: Some(Source::WebRip),
+                container: Some(Mkv),
+                ..Default::default()
+            },
+        ),
+        (
+            "[VCB-Studio] Sousou no Frieren [01-28][Ma10p_1080p][x265_flac]",
+            ParsedRelease {
+                group: s("VCB-Studio"),
+                name: s("Sousou no Frieren"),
+                batch: Some((1, 28)),
+                resolution: Some(1080),
+                codec: Some(H265),
+                ..Default::default()
+            },
+        ),
+        (
+            "[Moozzi2] Bocchi the Rock! [01-12 + SP] (BD 1920x1080 x.264 Flac)",
+            ParsedRelease {
+                group: s("Moozzi2"),
+                name: s("Bocchi the Rock!"),
+                batch: Some((1, 12)),
+                resolution: Some(1080),
+                source: Some(Source::BluRay),
+                ..Default::default()
+            },
+        ),
+        (
+            "[澄空学园&华盟字幕社] 孤独摇滚 第01-12话 全集 [简体中文][1080p][BDRip]",
+            ParsedRelease {
+                group: s("澄空学园&华盟字幕社"),
+                name: s("孤独摇滚"),
+                batch: Some((1, 12)),
+                resolution: Some(1080),
+                source: Some(Source::BluRay),
+                subtitles: vec![Chs],
+                ..Default::default()
+            },
+        ),
+        (
+            "Sousou no Frieren S01E03 1080p NF WEB-DL DDP2.0 H.264-VARYG",
+            ParsedRelease {
+                name: s("Sousou no Frieren"),
+                episode: Some(3),
+                resolution: Some(1080),
+                codec: Some(H264),
+                source: Some(Source::Web),
+                ..Default::default()
+            },
+        ),
+        (
+            "Dungeon Meshi E05 [1080p][HEVC][Multi-Subs]",
+            ParsedRelease {
+                name: s("Dungeon Meshi"),
+                episode: Some(5),
+                resolution: Some(1080),
+                codec: Some(H265),
+                ..Default::default()
+            },
+        ),
+        (
+            "[Judas] Jujutsu Kaisen - S02E10 [1080p][HEVC x265 10bit][Multi-Subs] (Weekly)",
+            ParsedRelease {
+                group: s("Judas"),
+                name: s("Jujutsu Kaisen"),
+                episode: Some(10),
+                resolution: Some(1080),
+                codec: Some(H265),
+                ..Default::default()
+            },
+        ),
+        (
+            "[DBD-Raws][葬送的芙莉莲/Sousou no Frieren][01-28TV全集][1080P][BDRip][HEVC-10bit][简繁日双语外挂][FLAC][MKV]",
+            ParsedRelease {
+                group: s("DBD-Raws"),
+                name: s("葬送的芙莉莲/Sousou no Frieren"),
+                batch: Some((1, 28)),
+                resolution: Some(1080),
+                codec: Some(H265),
+                source: Some(Source::BluRay),
+                subtitles: vec![Chs, Cht, Jpn],
+                container: Some(Mkv),
+                ..Default::default()
+            },
+        ),
+        (
+            "[SweetSub][总之就是非常可爱 第二季][Tonikaku Kawaii S2][07][WebRip][1080P][AVC 8bit][简日双语]",
+            ParsedRelease {
+                group: s("SweetSub"),
+                name: s("总之就是非常可爱 第二季"),
+                episode: Some(7),
+                resolution: Some(1080),
+                codec: Some(H264),
+                source: Some(Source::WebRip),
+                subtitles: vec![Chs, Jpn],
+                ..Default::default()
+            },
+        ),
+        (
+            "[GM-Team][国漫][斗罗大陆][Douluo Dalu][2019][263][AVC][GB][1080P]",
+            ParsedRelease {
+                group: s("GM-Team"),
+                name: s("国漫"),
+                episode: Some(263),
+                resolution: Some(1080),
+                codec: Some(H264),
+                subtitles: vec![Chs],
+                ..Default::default()
+            },
+        ),
+        (
+            "[Skymoon-Raws] One Piece 海贼王 - 1089 [ViuTV][WEB-DL][CHT][1080p][AVC AAC]",
+            ParsedRelease {
+                group: s("Skymoon-Raws"),
+                name: s("One Piece 海贼王"),
+                episode: Some(1089),
+                resolution: Some(1080),
+                codec: Some(H264),
+                source: Some(Source::Web),
+                subtitles: vec![Cht],
+                ..Default::default()
+            },
+        ),
+        (
+            "[织梦字幕组][尼尔：自动人形 Ver1.1a NieR Automata Ver1.1a][第十二集][1080P][AVC][简日双语]",
+            ParsedRelease {
+                group: s("织梦字幕组"),
+                name: s("尼尔:自动人形 Ver1.1a NieR Automata Ver1.1a"),
+                episode: Some(12),
+                resolution: Some(1080),
+                codec: Some(H264),
+                subtitles: vec![Chs, Jpn],
+                ..Default::default()
+            },
+        ),
+        (
+            "[ASW] Kaiju No. 8 - 03 [1080p HEVC x265 10Bit][AAC]",
+            ParsedRelease {
+                group: s("ASW"),
+                name: s("Kaiju No. 8"),
+                episode: Some(3),
+                resolution: Some(1080),
+                codec: Some(H265),
+                ..Default::default()
+            },
+        ),
+        (
+            "[Sakurato] Kaiju No. 8 [04][AVC-8bit 1080p AAC][CHS]",
+            ParsedRelease {
+                group: s("Sakurato"),
+                name: s("Kaiju No. 8"),
+                episode: Some(4),
+                resolution: Some(1080),
+                codec: Some(H264),
+                subtitles: vec![Chs],
+                ..Default::default()
+            },
+        ),
+        (
+            "[EMBER] 86 - Eighty Six (2021) (Season 1) [BDRip] [1080p Dual Audio HEVC 10 bits] (86 - Eighty Six S01) (Batch)",
+            ParsedRelease {
+                group: s("EMBER"),
+                name: s("86 - Eighty Six"),
+                resolution: Some(1080),
+                codec: Some(H265),
+                source: Some(Source::BluRay),
+                ..Default::default()
+            },
+        ),
+        (
+            "[Sakurato] Spy x Family Season 2 [10v2][AVC-8bit 1080p AAC][CHT]",
+            ParsedRelease {
+                group: s("Sakurato"),
+                name: s("Spy x Family Season 2"),
+                episode: Some(10),
+                version: Some(2),
+                resolution: Some(1080),
+                codec: Some(H264),
+                subtitles: vec![Cht],
+                ..Default::default()
+            },
+        ),
+        (
+            "[千夏字幕组][我心里危险的东西_Boku no Kokoro no Yabai Yatsu][第13话][1080p_AVC][简繁外挂]",
+            ParsedRelease {
+                group: s("千夏字幕组"),
+                name: s("我心里危险的东西_Boku no Kokoro no Yabai Yatsu"),
+                episode: Some(13),
+                resolution: Some(1080),
+                codec: Some(H264),
+                subtitles: vec![Chs, Cht],
+                ..Default::default()
+            },
+        ),
+        (
+            "[H-Enc] 葬送的芙莉莲 / Sousou no Frieren (BDRip 1920x1080 HEVC-YUV420P10 FLAC)",
+            ParsedRelease {
+                group: s("H-Enc"),
+                name: s("葬送的芙莉莲 / Sousou no Frieren"),
+                resolution: Some(1080),
+                codec: Some(H265),
+                source: Some(Source::BluRay),
+                ..Default::default()
+            },
+        ),
+        (
+            "[orion origin] Shangri-La Frontier [15] [1080p] [H265 AAC] [CHS].mp4",
+            ParsedRelease {
+                group: s("orion origin"),
+                name: s("Shangri-La Frontier"),
+                episode: Some(15),
+                resolution: Some(1080),
+                codec: Some(H265),
+                subtitles: vec![Chs],
+                container: Some(Mp4),
+                ..Default::default()
+            },
+        ),
+        (
+            "[Up to 21°C] 迷宫饭 / Dungeon Meshi - 01 (ABEMA 1920x1080 AVC AAC MP4)",
+            ParsedRelease {
+                group: s("Up to 21°C"),
+                name: s("迷宫饭 / Dungeon Meshi"),
+                episode: Some(1),
+                resolution: Some(1080),
+                codec: Some(H264),
+                source: Some(Source::Web),
+                container: Some(Mp4),
+                ..Default::default()
+            },
+        ),
+        (
+            "[MingY] 葬送的芙莉莲 / Sousou no Frieren [26][1080p][CHS&JPN]",
+            ParsedRelease {
+                group: s("MingY"),
+                name: s("葬送的芙莉莲 / Sousou no Frieren"),
+                episode: Some(26),
+                resolution: Some(1080),
+                subtitles: vec![Chs, Jpn],
+                ..Default::default()
+            },
+        ),
+        (
+            "[Kamigami] Steins;Gate 0 - 05 [1920x1080 x264 AAC Sub(Chs,Cht,Jap)].mkv",
+            ParsedRelease {
+                group: s("Kamigami"),
+                name: s("Steins;Gate 0"),
+                episode: Some(5),
+                resolution: Some(1080),
+                codec: Some(H264),
+                container: Some(Mkv),
+                subtitles: vec![Chs, Cht, Jpn],
+                ..Default::default()
+            },
+        ),
+        (
+            "[豌豆字幕组&LoliHouse] 怪兽8号 / Kaijuu 8-gou - 06 [WebRip 1080p HEVC-10bit AAC][简繁外挂字幕]",
+            ParsedRelease {
+                group: s("豌豆字幕组&LoliHouse"),
+                name: s("怪兽8号 / Kaijuu 8-gou"),
+                episode: Some(6),
+                resolution: Some(1080),
+                codec: Some(H265),
+                source: Some(Source::WebRip),
+                subtitles: vec![Chs, Cht],
+                ..Default::default()
+            },
+        ),
+        (
+            "[Airota][Yuru Camp Season 3][09][BDRip 2160p AVC-10bit FLAC][CHS_JP]",
+            ParsedRelease {
+                group: s("Airota"),
+                name: s("Yuru Camp Season 3"),
+                episode: Some(9),
+                resolution: Some(2160),
+                codec: Some(H264),
+                source: Some(Source::BluRay),
+                subtitles: vec![Chs, Jpn],
+                ..Default::default()
+            },
+        ),
+        (
+            "[Haruhana] Hibike! Euphonium 3 - 11 [WebRip][HEVC-10bit 4K][CHS&CHT&JPN]",
+            ParsedRelease {
+                group: s("Haruhana"),
+                name: s("Hibike! Euphonium 3"),
+                episode: Some(11),
+                resolution: Some(2160),
+                codec: Some(H265),
+                source: Some(Source::WebRip),
+                subtitles: vec![Chs, Cht, Jpn],
+                ..Default::default()
+            },
+        ),
+        (
+            "Oshi no Ko - 第03话 [简繁中字][720p][TV]",
+            ParsedRelease {
+                name: s("Oshi no Ko"),
+                episode: Some(3),
+                resolution: Some(720),
+                source: Some(Source::Tv),
+                subtitles: vec![Chs, Cht],
+                ..Default::default()
+            },
+        ),
+        (
+            "[DMG&LoliHouse] Frieren 全28话 [BDRip 1080p AV1 OPUS][简繁日内封]",
+            ParsedRelease {
+                group: s("DMG&LoliHouse"),
+                name: s("Frieren"),
+                batch: Some((1, 28)),
+                resolution: Some(1080),
+                codec: Some(Av1),
+                source: Some(Source::BluRay),
+                subtitles: vec![Chs, Cht, Jpn],
+                ..Default::default()
+            },
+        ),
+        (
+            "[Snow-Raws] 某科学的超电磁炮T 第01話 (BD 1920x1080 HEVC-YUV420P10 FLACx2)",
+            ParsedRelease {
+                group: s("Snow-Raws"),
+                name: s("某科学的超电磁炮T"),
+                episode: Some(1),
+                resolution: Some(1080),
+                codec: Some(H265),
+                source: Some(Source::BluRay),
+                ..Default::default()
+            },
+        ),
+        (
+            "[Ohys-Raws] Sousou no Frieren - 09 (TBS 1280x720 x264 AAC).mp4",
+            ParsedRelease {
+                group: s("Ohys-Raws"),
+                name: s("Sousou no Frieren"),
+                episode: Some(9),
+                resolution: Some(720),
+                codec: Some(H264),
+                container: Some(Mp4),
+                ..Default::default()
+            },
+        ),
+        (
+            "[Kamigami&VCB-Studio] Shingeki no Kyojin [DVDRip 480p x264 AAC]",
+            ParsedRelease {
+                group: s("Kamigami&VCB-Studio"),
+                name: s("Shingeki no Kyojin"),
+                resolution: Some(480),
+                codec: Some(H264),
+                source: Some(Source::Dvd),
+                ..Default::default()
+            },
+        ),
+    ];
+    for (title, expected) in cases {
+        assert_eq!(parse(title), expected, "{title}");
+    }
+}
+
+#[test]
+fn episodes() {
+    let cases = [
+        ("[Group] Name - 01 [1080p]", Some(1)),
+        ("[Group] Name - 1 [1080p]", Some(1)),
+        ("[Group] Name - 100 [1080p]", Some(100)),
+        ("[Group] Name - 1100 (1080p)", Some(1100)),
+        ("[Group] Name - 07.mkv", Some(7)),
+        ("[Group] Name - 07", Some(7)),
+        ("[Group] Name - 07v3", Some(7)),
+        ("[Group][Name][07]", Some(7)),
+        ("[Group][Name][07v2][1080p]", Some(7)),
+        ("[Group][Name][07END][1080p]", Some(7)),
+        ("[Group][Name][12 END][1080p]", Some(12)),
+        ("[Group][Name][12完][1080p]", Some(12)),
+        ("[Group][Name][2024][07][1080p]", Some(7)),
+        ("[Group] Name [07] [1080p]", Some(7)),
+        ("[Group] Name 07 [1080p]", Some(7)),
+        ("[Group] Name Season 2 07 [1080p]", Some(7)),
+        ("[Group] Name 2nd Season - 07 [1080p]", Some(7)),
+        ("[Group] Name 第07话 [1080p]", Some(7)),
+        ("[Group] Name 第7话 [1080p]", Some(7)),
+        ("[Group] Name 第07話 [1080p]", Some(7)),
+        ("[Group] Name 第07集 [1080p]", Some(7)),
+        ("[Group] Name 第 07 话 [1080p]", Some(7)),
+        ("[Group] Name 第０７话 [1080p]", Some(7)),
+        ("[Group] Name 第七话 [1080p]", Some(7)),
+        ("[Group] Name 第十话 [1080p]", Some(10)),
+        ("[Group] Name 第十二话 [1080p]", Some(12)),
+        ("[Group] Name 第二十四话 [1080p]", Some(24)),
+        ("[Group] Name 第一百零五话 [1080p]", Some(105)),
+        ("[Group][Name][第03话][1080p]", Some(3)),
+        ("【Group】【Name】【03】【1080P】", Some(3)),
+        ("［Group］［Name］［０３］［１０８０Ｐ］", Some(3)),
+        ("Name S01E07 1080p WEB-DL", Some(7)),
+        ("Name s2e13 720p", Some(13)),
+        ("Name EP07 1080p", Some(7)),
+        ("Name Ep.07 1080p", Some(7)),
+        ("Name Episode 7 [1080p]", Some(7)),
+        ("Name E07v2 [1080p]", Some(7)),
+        ("[Group] Name - 07 (1920x1080 x264)", Some(7)),
+        ("[Group] Name [1080p]", None),
+        ("[Group] Name (2024) [1080p]", None),
+        ("[Group] Name [x264 1080p]", None),
+        ("[Group] Name [H.264 1080p]", None),
+        ("[Group] Name [HEVC-10bit 1080p]", None),
+        ("[Group] Name [01-12] [1080p]", None),
+    ];
+    for (title, episode) in cases {
+        assert_eq!(parse(title).episode, episode, "{title}");
+    }
+}
+
+#[test]
+fn batches() {
+    let cases = [
+        ("[Group] Name [01-12] [1080p]", Some((1, 12))),
+        ("[Group] Name [01~12] [1080p]", Some((1, 12))),
+        ("[Group] Name [01-12 Fin] [1080p]", Some((1, 12))),
+        ("[Group] Name [01-24+SP] [1080p]", Some((1, 24))),
+        ("[Group] Name [01-13 + OVA] [1080p]", Some((1, 13))),
+        ("[Group] Name [13-24合集] [1080p]", Some((13, 24))),
+        ("[Group] Name 第01-12话 [1080p]", Some((1, 12))),
+        ("[Group] Name 第１３－２４話 [1080p]", Some((13, 24))),
+        ("[Group] Name 全12话 [1080p]", Some((1, 12))),
+        ("[Group] Name 全 24 集 [1080p]", Some((1, 24))),
+        ("[Group] Name 01-12 [1080p]", Some((1, 12))),
+        ("Name 01~26 BDRip 1080p", Some((1, 26))),
+        ("[Group] Name - 07 [1080p]", None),
+        ("[Group] Name [2023-10] [1080p]", None),
+        ("[Group] Name [12-01] [1080p]", None),
+    ];
+    for (title, batch) in cases {
+        let parsed = parse(title);
+        assert_eq!(parsed.batch, batch, "{title}");
+        if batch.is_some() {
+            assert_eq!(parsed.episode, None, "{title}");
+        }
+    }
+}
+
+#[test]
+fn versions() {
+    let cases = [
+        ("[Group] Name - 07v2 [1080p]", Some(2)),
+        ("[Group] Name - 07 v2 [1080p]", Some(2)),
+        ("[Group][Name][07v3][1080p]", Some(3)),
+        ("[Group][Name][07][V2][1080p]", Some(2)),
+        ("[Group] Name 第07话v2 [1080p]", Some(2)),
+        ("Name S01E07v2 1080p", Some(2)),
+        ("[Group] Name - 07 [1080p]", None),
+        ("[Group] Name - 07 [1080p][HEVC]", None),
+    ];
+    for (title, version) in cases {
+        assert_eq!(parse(title).version, version, "{title}");
+    }
+}
+
+#[test]
+fn groups() {
+    let cases = [
+        ("[SubsPlease] Name - 01 (1080p)", s("SubsPlease")),
+        ("【喵萌奶茶屋】★10月新番★[Name][01]", s("喵萌奶茶屋")),
+        ("[10月新番][Group] Name - 01", s("Group")),
+        ("★10月新番★[Group][Name][01]", s("Group")),
+        ("[Group A&Group B] Name - 01", s("Group A&Group B")),
+        ("  [Group] Name - 01", s("Group")),
+        ("(Group) Name - 01", s("Group")),
+        ("Name - 01 [1080p]", None),
+        ("Name S01E01 1080p", None),
+    ];
+    for (title, group) in cases {
+        assert_eq!(parse(title).group, group, "{title}");
+    }
+}
+
+#[test]
+fn names() {
+    let cases = [
+        ("[Group] Name - 01 (1080p)", s("Name")),
+        ("[Group] Some Long Name - 01 (1080p)", s("Some Long Name")),
+        ("[Group] 名字 / Name - 01 (1080p)", s("名字 / Name")),
+        ("[Group][名字][01][1080p]", s("名字")),
+        ("[Group][名字 / Name][01][1080p]", s("名字 / Name")),
+        ("[Group] 名字 第01话 [1080p]", s("名字")),
+        ("[Group] 名字 [01][1080p]", s("名字")),
+        ("[Group] Name 01 [1080p]", s("Name")),
+        ("[Group] Name [1080p]", s("Name")),
+        ("[Group] Name 1080p WEB-DL", s("Name")),
+        ("Name S01E01 1080p", s("Name")),
+        ("Name - 01 [1080p]", s("Name")),
+        (
+            "[Group] Re:Zero kara Hajimeru Isekai Seikatsu - 50 [1080p]",
+            s("Re:Zero kara Hajimeru Isekai Seikatsu"),
+        ),
+        ("[Group] Name  with   spaces - 01", s("Name with spaces")),
+        ("[Group][01][Name][1080p]", s("Name")),
+        ("[Group][2024][Name][01]", s("Name")),
+        ("[Group] Mob Psycho 100 - 01 [1080p]", s("Mob Psycho 100")),
+        ("[Group] Name.mkv", s("Name")),
+    ];
+    for (title, name) in cases {
+        assert_eq!(parse(title).name, name, "{title}");
+    }
+}
+
+#[test]
+fn resolutions() {
+    let cases = [
+        ("[G] Name - 01 [1080p]", Some(1080)),
+        ("[G] Name - 01 [1080P]", Some(1080)),
+        ("[G] Name - 01 [720p]", Some(720)),
+        ("[G] Name - 01 [480p]", Some(480)),
+        ("[G] Name - 01 [2160p]", Some(2160)),
+        ("[G] Name - 01 [4K]", Some(2160)),
+        ("[G] Name - 01 [4k HEVC]", Some(2160)),
+        ("[G] Name - 01 [1920x1080]", Some(1080)),
+        ("[G] Name - 01 [1920X1080]", Some(1080)),
+        ("[G] Name - 01 [1920×1080]", Some(1080)),
+        ("[G] Name - 01 [1280x720]", Some(720)),
+        ("[G] Name - 01 [3840x2160]", Some(2160)),
+        ("[G] Name - 01 [１０８０Ｐ]", Some(1080)),
+        ("[G] Name - 01 [1080i]", Some(1080)),
+        ("[G] Name - 01 [Ma10p_1080p]", Some(1080)),
+        ("[G][Name][01][BIG5_1080P]", Some(1080)),
+        ("[G] Name - 01", None),
+        ("[G] Name - 1080 [x264]", None),
+    ];
+    for (title, resolution) in cases {
+        assert_eq!(parse(title).resolution, resolution, "{title}");
+    }
+}
+
+#[test]
+fn codecs() {
+    let cases = [
+        ("[G] Name - 01 [x264]", Some(H264)),
+        ("[G] Name - 01 [H264]", Some(H264)),
+        ("[G] Name - 01 [H.264]", Some(H264)),
+        ("[G] Name - 01 [AVC AAC]", Some(H264)),
+        ("[G] Name - 01 [AVC-8bit]", Some(H264)),
+        ("[G] Name - 01 [x265]", Some(H265)),
+        ("[G] Name - 01 [H265]", Some(H265)),
+        ("[G] Name - 01 [H.265]", Some(H265)),
+        ("[G] Name - 01 [HEVC-10bit]", Some(H265)),
+        ("[G] Name - 01 [HEVC_AAC]", Some(H265)),
+        ("[G] Name - 01 [AV1]", Some(Av1)),
+        ("[G] Name - 01 [1080p]", None),
+        ("[G] Javelin - 01 [1080p]", None),
+    ];
+    for (title, codec) in cases {
+        assert_eq!(parse(title).codec, codec, "{title}");
+    }
+}
+
+#[test]
+fn sources() {
+    let cases = [
+        ("[G] Name - 01 [WEB-DL]", Some(Source::Web)),
+        ("[G] Name - 01 [WEBDL]", Some(Source::Web)),
+        ("[G] Name - 01 [Baha][1080p]", Some(Source::Web)),
+        ("[G] Name - 01 (CR 1080p)", Some(Source::Web)),
+        ("[G] Name - 01 (B-Global 1080p)", Some(Source::Web)),
+        ("[G] Name - 01 [WebRip]", Some(Source::WebRip)),
+        ("[G] Name - 01 [WEB-Rip]", Some(Source::WebRip)),
+        ("[G] Name - 01 [BDRip]", Some(Source::BluRay)),
+        ("[G] Name - 01 [BD-Rip]", Some(Source::BluRay)),
+        ("[G] Name - 01 [BD 1080p]", Some(Source::BluRay)),
+        ("[G] Name - 01 [Blu-ray]", Some(Source::BluRay)),
+        ("[G] Name - 01 [BluRay]", Some(Source::BluRay)),
+        ("[G] Name - 01 [HDTV]", Some(Source::Tv)),
+        ("[G] Name - 01 [TVRip]", Some(Source::Tv)),
+        ("[G] Name - 01 [DVDRip]", Some(Source::Dvd)),
+        ("[G] Name - 01 [DVD]", Some(Source::Dvd)),
+        ("[G] Name - 01 [1080p]", None),
+        ("[G] Webcam Girls - 01 [1080p]", None),
+    ];
+    for (title, source) in cases {
+        assert_eq!(parse(title).source, source, "{title}");
+    }
+}
+
+#[test]
+fn subtitles() {
+    let cases = [
+        ("[G] Name - 01 [CHS]", vec![Chs]),
+        ("[G] Name - 01 [CHT]", vec![Cht]),
+        ("[G] Name - 01 [GB]", vec![Chs]),
+        ("[G] Name - 01 [BIG5]", vec![Cht]),
+        ("[G] Name - 01 [GB_MP4]", vec![Chs]),
+        ("[G] Name - 01 [简体]", vec![Chs]),
+        ("[G] Name - 01 [繁体]", vec![Cht]),
+        ("[G] Name - 01 [繁體]", vec![Cht]),
+        ("[G] Name - 01 [简繁]", vec![Chs, Cht]),
+        ("[G] Name - 01 [简繁内封]", vec![Chs, Cht]),
+        ("[G] Name - 01 [简日双语]", vec![Chs, Jpn]),
+        ("[G] Name - 01 [繁日双语]", vec![Cht, Jpn]),
+        ("[G] Name - 01 [中日双语]", vec![Chs, Jpn]),
+        ("[G] Name - 01 [简繁日内封字幕]", vec![Chs, Cht, Jpn]),
+        ("[G] Name - 01 [简中]", vec![Chs]),
+        ("[G] Name - 01 [繁中]", vec![Cht]),
+        ("[G] Name - 01 [CHS&CHT]", vec![Chs, Cht]),
+        ("[G] Name - 01 [CHS_JP]", vec![Chs, Jpn]),
+        ("[G] Name - 01 [JPSC]", vec![Jpn, Chs]),
+        ("[G] Name - 01 [JPTC]", vec![Jpn, Cht]),
+        ("[G] Name - 01 [ENG]", vec![Eng]),
+        ("[G] Name - 01 [中英双语]", vec![Chs, Eng]),
+        ("[G] Name - 01 [CHS][CHS]", vec![Chs]),
+        ("[G] 日常 - 01 [1080p]", vec![]),
+        ("[G] Name - 01 [1.2GB]", vec![]),
+        ("[G] Name - 01 [1080p]", vec![]),
+    ];
+    for (title, subtitles) in cases {
+        assert_eq!(parse(title).subtitles, subtitles, "{title}");
+    }
+}
+
+#[test]
+fn containers() {
+    let cases = [
+        ("[G] Name - 01 [1080p].mkv", Some(Mkv)),
+        ("[G] Name - 01 [1080p].MP4", Some(Mp4)),
+        ("[G] Name - 01 [1080p].avi", Some(Avi)),
+        ("[G] Name - 01 [1080p][MKV]", Some(Mkv)),
+        ("[G] Name - 01 [1080p][GB_MP4]", Some(Mp4)),
+        ("[G] Name - 01 (AVC AAC MP4)", Some(Mp4)),
+        ("[G] Name - 01 [1080p]", None),
+    ];
+    for (title, container) in cases {
+        assert_eq!(parse(title).container, container, "{title}");
+    }
+}
+
+#[test]
+fn normalizes_full_width() {
+    assert_eq!(normalize("［Ｇｒｏｕｐ］　第０３话"), "[Group] 第03话");
+    assert_eq!(normalize("【Group】（ＢＤ）"), "[Group](BD)");
+}
+
+#[test]
+fn specials() {
+    use Special::*;
+    let cases = [
+        ("[Group] Name - 12.5 [1080p]", Some(Fraction(12, 5))),
+        ("[Group][Name][12.5][1080p]", Some(Fraction(12, 5))),
+        ("[Group] Name 第12.5话 [1080p]", Some(Fraction(12, 5))),
+        ("[Group] Name - SP [1080p]", Some(Sp(1))),
+        ("[Group] Name - SP02 [1080p]", Some(Sp(2))),
+        ("[Group][Name][SP 2][1080p]", Some(Sp(2))),
+        ("[Group] Name OVA [1080p]", Some(Ova(1))),
+        ("[Group] Name OAD - 02 [1080p]", Some(Ova(2))),
+        ("[Group][Name][特别篇][1080p]", Some(Sp(1))),
+        ("[Group][Name][番外01][1080p]", Some(Sp(1))),
+        ("[Group][Name][总集篇][1080p]", Some(Recap)),
+        ("[Group] Name Recap [1080p]", Some(Recap)),
+        ("[Group] Name - 12 [1080p][AAC2.0]", None),
+        ("[Group] Name - 12 [1080p][DDP5.1]", None),
+        ("[Group] SPY x FAMILY - 03 [1080p]", None),
+        ("[Group] Name [01-12+SP] [1080p]", None),
+        ("[Group] Name - 03 [1080p][5.1]", None),
+        ("[Group] Name - 03 [1080p][SP]", None),
+    ];
+    for (title, special) in cases {
+        let parsed = parse(title);
+        assert_eq!(parsed.special, special, "{title}");
+        if special.is_some() {
+            assert_eq!(parsed.episode, None, "{title}");
+        }
+    }
+    assert!(!parse("[Group] Name - 12.5 [1080p]").covers(12));
+    assert!(parse("[Group] Name - 03 [1080p][5.1]").covers(3));
+}
+
+#[test]
+fn special_labels() {
+    use Special::*;
+    for (label, special) in [
+        ("sp", Sp(1)),
+        ("SP2", Sp(2)),
+        ("ova3", Ova(3)),
+        ("oad", Ova(1)),
+        ("recap", Recap),
+        ("12.5", Fraction(12, 5)),
+    ] {
+        assert_eq!(label.parse::<Special>().ok(), Some(special), "{label}");
+    }
+    assert_eq!(Fraction(12, 5).to_string(), "12.5");
+    assert_eq!(Sp(2).to_string(), "sp2");
+    for label in ["", "12", "12.0", "spx", "ep3"] {
+        assert!(label.parse::<Special>().is_err(), "{label}");
+    }
+}
+
+#[test]
+fn chinese_numbers() {
+    let cases = [
+        ("一", Some(1)),
+        ("九", Some(9)),
+        ("十", Some(10)),
+        ("十一", Some(11)),
+        ("二十", Some(20)),
+        ("二十四", Some(24)),
+        ("一百", Some(100)),
+        ("一百零五", Some(105)),
+        ("两百", Some(200)),
+        ("〇", Some(0)),
+        ("第", None),
+    ];
+    for (s, n) in cases {
+        assert_eq!(cn_number(s), n, "{s}");
+    }
+}
+
+#[test]
+fn covers() {
+    let single = parse("[G] Name - 03 [1080p]");
+    assert!(single.covers(3));
+    assert!(!single.covers(4));
+    let batch = parse("[G] Name [01-12] [1080p]");
+    assert!(batch.covers(1));
+    assert!(batch.covers(12));
+    assert!(!batch.covers(13));
+    assert!(parse("[G] Name [1080p]").covers(7));
+}
@@ -0,0 +1,253 @@
+use crate::config::TransmissionConfig;
+use crate::downloader::{DownloadFile, DownloadState, DownloadStatus, Downloader};
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::debug;
+
+const SESSION_HEADER: &str = "X-Transmission-Session-Id";
+const STATUS_FIELDS: &[&str] = &[
+    "hashString",
+    "status",
+    "error",
+    "errorString",
+    "sizeWhenDone",
+    "leftUntilDone",
+];
+
+#[derive(Deserialize)]
+struct RpcRsp {
+    result: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Added {
+    hash_string: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TorrentInfo {
+    hash_string: String,
+    /// 0 stopped, 1 check wait, 2 check, 3 download wait, 4 download,
+    /// 5 seed wait, 6 seed
+    status: u8,
+    /// 0 none, 1 tracker warning, 2 tracker error, 3 local error
+    error: u8,
+    error_string: String,
+    size_when_done: u64,
+    left_until_done: u64,
+}
+
+#[derive(Deserialize)]
+struct TorrentFiles {
+    files: Vec<File>,
+    #[serde(rename = "fileStats")]
+    file_stats: Vec<FileStat>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct File {
+    name: String,
+    length: u64,
+    bytes_completed: u64,
+}
+
+#[derive(Deserialize)]
+struct FileStat {
+    wanted: bool,
+}
+
+/// Client of the Transmission RPC. Downloads are identified by their
+/// infohash.
+pub struct Transmission {
+    client: Client,
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+    /// handed out by the daemon on a 409, sent with every request after
+    session: Mutex<Option<String>>,
+    poll: Duration,
+}
+
+impl Transmission {
+    pub fn new(cfg: &TransmissionConfig) -> Self {
+        Transmission {
+            client: Client::new(),
+            url: cfg.url.clone(),
+            username: cfg.username.clone(),
+            password: cfg.password.clone(),
+            session: Mutex::new(None),
+            poll: Duration::from_secs(cfg.poll_secs),
+        }
+    }
+
+    async fn post(&self, body: &Value) -> Result<reqwest::Response> {
+        let mut req = self.client.post(&self.url).json(body);
+        if let Some(username) = &self.username {
+            req = req.basic_auth(username, self.password.as_ref());
+        }
+        let session = self
+            .session
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        if let Some(session) = session {
+            req = req.header(SESSION_HEADER, session);
+        }
+        req.send().await.map_err(Error::Transmission)
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, arguments: Value) -> Result<T> {
+        let body = json!({ "method": method, "arguments": arguments });
+        let mut rsp = self.post(&body).await?;
+        if rsp.status() == StatusCode::CONFLICT {
+            // missing or outdated session id, retry with the one we were given
+            let session = rsp
+                .headers()
+                .get(SESSION_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string);
+            debug!("transmission session id: {:?}", session);
+            *self.session.lock().unwrap_or_else(|e| e.into_inner()) = session;
+            rsp = self.post(&body).await?;
+        }
+        let rsp: RpcRsp = rsp
+            .error_for_status()
+            .map_err(Error::Transmission)?
+            .json()
+            .await
+            .map_err(Error::Transmission)?;
+        if rsp.result != "success" {
+            return Err(Error::TransmissionRpc(format!("{method}: {}", rsp.result)));
+        }
+        serde_json::from_value(rsp.arguments).map_err(Error::parse)
+    }
+
+    async fn torrent<T: DeserializeOwned>(&self, id: &str, fields: &[&str]) -> Result<Option<T>> {
+        #[derive(Deserialize)]
+        struct Torrents<T> {
+            torrents: Vec<T>,
+        }
+        let rsp: Torrents<T> = self
+            .call("torrent-get", json!({ "ids": [id], "fields": fields }))
+            .await?;
+        Ok(rsp.torrents.into_iter().next())
+    }
+}
+
+#[async_trait]
+impl Downloader for Transmission {
+    async fn add(&self, uri: &str, dir: &str, _name: Option<&str>) -> Result<String> {
+        let mut rsp: serde_json::Map<String, Value> = self
+            .call(
+                "torrent-add",
+                json!({ "filename": uri, "download-dir": dir }),
+            )
+            .await?;
+        let added = rsp
+            .remove("torrent-added")
+            .or_else(|| rsp.remove("torrent-duplicate"))
+            .ok_or_else(|| Error::TransmissionRpc(format!("torrent-add {uri}: no torrent")))?;
+        let added: Added = serde_json::from_value(added).map_err(Error::parse)?;
+        Ok(added.hash_string)
+    }
+
+    async fn status(&self, id: &str) -> Result<DownloadStatus> {
+        let Some(t) = self.torrent::<TorrentInfo>(id, STATUS_FIELDS).await? else {
+            return Ok(DownloadStatus {
+                id: id.to_string(),
+                state: DownloadState::Removed,
+                total: 0,
+                completed: 0,
+                info_hash: Some(id.to_string()),
+                followed_by: None,
+            });
+        };
+        // tracker warnings and errors don't stop the download, local ones do
+        let state = if t.error == 3 {
+            DownloadState::Failed(t.error_string)
+        } else {
+            match t.status {
+                0 if t.size_when_done > 0 && t.left_until_done == 0 => DownloadState::Complete,
+                0 => DownloadState::Paused,
+                1..=3 => DownloadState::Waiting,
+                4 => DownloadState::Active,
+                _ => DownloadState::Complete,
+            }
+        };
+        Ok(DownloadStatus {
+            id: t.hash_string.clone(),
+            state,
+            total: t.size_when_done,
+            completed: t.size_when_done - t.left_until_done.min(t.size_when_done),
+            info_hash: Some(t.hash_string),
+            followed_by: None,
+        })
+    }
+
+    async fn pause(&self, id: &str) -> Result<()> {
+        let _: Value = self.call("torrent-stop", json!({ "ids": [id] })).await?;
+        Ok(())
+    }
+
+    async fn remove(&self, id: &str) -> Result<()> {
+        let _: Value = self
+            .call(
+                "torrent-remove",
+                json!({ "ids": [id], "delete-local-data": false }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn files(&self, id: &str) -> Result<Vec<DownloadFile>> {
+        let Some(t) = self
+            .torrent::<TorrentFiles>(id, &["files", "fileStats"])
+            .await?
+        else {
+            return Err(Error::NotFound(format!("torrent {id}")));
+        };
+        Ok(t.files
+            .into_iter()
+            .zip(t.file_stats)
+            .enumerate()
+            .map(|(i, (f, s))| DownloadFile {
+                index: i as u32,
+                path: f.name,
+                length: f.length,
+                completed: f.bytes_completed,
+                selected: s.wanted,
+            })
+            .collect())
+    }
+
+    async fn select(&self, id: &str, indexes: &[u32]) -> Result<()> {
+        let unwanted: Vec<u32> = self
+            .files(id)
+            .await?
+            .iter()
+            .map(|f| f.index)
+            .filter(|i| !indexes.contains(i))
+            .collect();
+        let mut arguments = json!({ "ids": [id], "files-wanted": indexes });
+        // an empty list stands for every file
+        if !unwanted.is_empty() {
+            arguments["files-unwanted"] = json!(unwanted);
+        }
+        let _: Value = self.call("torrent-set", arguments).await?;
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.poll
+    }
+}
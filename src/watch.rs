@@ -0,0 +1,218 @@
+use crate::config::WatchConfig;
+use crate::downloader::{
+    magnet_info_hash, DownloadFile, DownloadState, DownloadStatus, Downloader,
+};
+use crate::error::{Error, Result};
+use crate::source;
+use async_trait::async_trait;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::info;
+
+/// Hands downloads to a client watching a folder: magnets are written as
+/// `.magnet` files, anything else is fetched and written as a `.torrent`.
+/// There is no way to ask the client about a download, so the id is the file
+/// or folder it is expected to produce, named after the first file of the
+/// torrent, the release or its infohash. Clients may create it under its
+/// final name when they start, so the download counts as complete once it
+/// keeps its size across two polls and nothing in it is partial.
+pub struct WatchFolder {
+    dir: PathBuf,
+    poll: Duration,
+    depth: usize,
+    /// size of the download of each id at the last poll
+    sizes: Mutex<HashMap<String, u64>>,
+}
+
+/// Suffixes clients give a file while they write it.
+const PARTIAL_SUFFIXES: [&str; 4] = [".part", ".!qB", ".!ut", ".crdownload"];
+
+impl WatchFolder {
+    pub fn new(cfg: &WatchConfig) -> Self {
+        WatchFolder {
+            dir: cfg.dir.clone(),
+            poll: Duration::from_secs(cfg.poll_secs),
+            depth: cfg.depth,
+            sizes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the client is still writing `path` under another name, or a
+    /// file of the folder at `path`.
+    fn has_partial(path: &Path) -> bool {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let partial = |p: &Path| {
+            PARTIAL_SUFFIXES
+                .iter()
+                .any(|suffix| p.to_string_lossy().ends_with(suffix))
+        };
+        PARTIAL_SUFFIXES
+            .iter()
+            .any(|suffix| path.with_file_name(format!("{name}{suffix}")).exists())
+            || files_below(path).iter().any(|f| partial(f))
+    }
+
+    /// Looks for a file or folder called `name`, extension aside, in `dir`
+    /// and its subfolders.
+    fn find(dir: &Path, name: &str, depth: usize) -> Option<PathBuf> {
+        for entry in std::fs::read_dir(dir).ok()?.flatten() {
+            let path = entry.path();
+            let named = entry.file_name() == name
+                || (path.is_file() && path.file_stem().is_some_and(|s| s == name));
+            if named {
+                return Some(path);
+            }
+            if path.is_dir() && depth > 0 {
+                if let Some(found) = Self::find(&path, name, depth - 1) {
+                    return Some(found);
+                }
+            }
+        }
+        None
+    }
+
+    fn expected(id: &str) -> (&Path, &str) {
+        let path = Path::new(id);
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or(id);
+        (dir, name)
+    }
+}
+
+/// The `dn` of a magnet, its display name.
+fn magnet_name(uri: &str) -> Option<String> {
+    let url = Url::parse(uri).ok()?;
+    let name = url.query_pairs().find(|(k, _)| k == "dn")?.1;
+    Some(name.into_owned())
+}
+
+/// Files of the folder at `path` and its subfolders, or `path` itself.
+fn files_below(path: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return vec![path.to_path_buf()];
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .flat_map(|entry| files_below(&entry.path()))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Bytes of the file or folder at `path`.
+fn size_of(path: &Path) -> u64 {
+    files_below(path)
+        .iter()
+        .filter_map(|f| f.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// `name` made safe to be used as a file name.
+fn file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| if "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect()
+}
+
+#[async_trait]
+impl Downloader for WatchFolder {
+    async fn add(&self, uri: &str, dir: &str, name: Option<&str>) -> Result<String> {
+        let Some(name) = name
+            .map(str::to_string)
+            .or_else(|| magnet_name(uri))
+            .or_else(|| magnet_info_hash(uri))
+        else {
+            return Err(Error::NotFound(format!("file name of {uri} to watch for")));
+        };
+        let name = file_stem(&name);
+        let stem = magnet_info_hash(uri).unwrap_or_else(|| name.clone());
+
+        let file = if uri.starts_with("magnet:") {
+            let file = self.dir.join(format!("{stem}.magnet"));
+            std::fs::write(&file, uri)?;
+            file
+        } else {
+            let body = source::client()?
+                .get(uri)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(Error::Source)?
+                .bytes()
+                .await
+                .map_err(Error::Source)?;
+            let file = self.dir.join(format!("{stem}.torrent"));
+            std::fs::write(&file, body)?;
+            file
+        };
+        info!("wrote {} to the watch folder", file.display());
+        Ok(Path::new(dir).join(name).to_string_lossy().into_owned())
+    }
+
+    async fn status(&self, id: &str) -> Result<DownloadStatus> {
+        let (dir, name) = Self::expected(id);
+        let found = Self::find(dir, name, self.depth);
+        let length = found.as_deref().map(size_of).unwrap_or_default();
+        let settled = found.as_ref().is_some_and(|file| {
+            let mut sizes = self.sizes.lock().unwrap_or_else(|e| e.into_inner());
+            let last = sizes.insert(id.to_string(), length);
+            let settled = last == Some(length) && !Self::has_partial(file);
+            if settled {
+                sizes.remove(id);
+            }
+            settled
+        });
+        Ok(DownloadStatus {
+            id: id.to_string(),
+            state: if settled {
+                DownloadState::Complete
+            } else {
+                DownloadState::Active
+            },
+            // the final size is unknown until the file stops growing
+            total: if settled { length } else { 0 },
+            completed: length,
+            info_hash: None,
+            followed_by: None,
+        })
+    }
+
+    async fn pause(&self, id: &str) -> Result<()> {
+        Err(Error::State(format!(
+            "{id} is downloaded by a watch folder client, which can't be paused"
+        )))
+    }
+
+    /// The client keeps the download, there is nothing to forget on our side.
+    async fn remove(&self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn files(&self, id: &str) -> Result<Vec<DownloadFile>> {
+        let (dir, name) = Self::expected(id);
+        Ok(Self::find(dir, name, self.depth)
+            .map(|found| files_below(&found))
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(index, path)| {
+                let length = path.metadata().map(|m| m.len()).unwrap_or_default();
+                DownloadFile {
+                    index: index as u32,
+                    path: path.to_string_lossy().into_owned(),
+                    length,
+                    completed: length,
+                    selected: true,
+                }
+            })
+            .collect())
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.poll
+    }
+}